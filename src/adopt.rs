@@ -0,0 +1,194 @@
+use crate::{git, session, store, tmux};
+use anyhow::{Context, Result};
+use std::path::Path;
+use std::process::Command;
+
+/// A running process, from `ps`
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Process {
+    pid: u32,
+    ppid: u32,
+    args: String,
+}
+
+fn parse_processes(ps: &str) -> Vec<Process> {
+    ps.lines()
+        .filter_map(|line| {
+            let mut fields = line.split_whitespace();
+            let pid = fields.next()?.parse().ok()?;
+            let ppid = fields.next()?.parse().ok()?;
+            let args = fields.collect::<Vec<_>>().join(" ");
+            Some(Process { pid, ppid, args })
+        })
+        .collect()
+}
+
+fn processes() -> Result<Vec<Process>> {
+    let output = Command::new("ps")
+        .args(["-A", "-o", "pid=,ppid=,args="])
+        .output()
+        .context("Failed to execute ps")?;
+    if !output.status.success() {
+        anyhow::bail!("ps failed");
+    }
+    Ok(parse_processes(&String::from_utf8_lossy(&output.stdout)))
+}
+
+/// Whether a command line runs Claude Code: the `claude` executable itself, or its
+/// script under an interpreter like node
+fn is_claude(args: &str) -> bool {
+    let is_claude_path = |word: &str| {
+        word.contains("@anthropic-ai/claude-code")
+            || Path::new(word)
+                .file_name()
+                .is_some_and(|name| name == "claude")
+    };
+    let mut words = args.split_whitespace();
+    // An interpreter's script is a path, which tells it apart from e.g. `grep claude`
+    words.next().is_some_and(is_claude_path)
+        || words
+            .next()
+            .is_some_and(|script| script.contains('/') && is_claude_path(script))
+}
+
+/// Whether `pid` or any of its descendants runs Claude Code
+fn runs_claude(pid: u32, processes: &[Process]) -> bool {
+    let mut pending = vec![pid];
+    let mut seen = Vec::new();
+    while let Some(pid) = pending.pop() {
+        if seen.contains(&pid) {
+            continue;
+        }
+        seen.push(pid);
+        for process in processes {
+            if process.pid == pid && is_claude(&process.args) {
+                return true;
+            }
+            if process.ppid == pid {
+                pending.push(process.pid);
+            }
+        }
+    }
+    false
+}
+
+/// The ccx session a pane already belongs to: one started by ccx, or one adopted
+/// earlier whose pane is still open
+fn owning_session(pane: &tmux::Pane) -> Result<Option<String>> {
+    if pane.other_server {
+        return Ok(None);
+    }
+    if store::load(&pane.session)?.is_some() {
+        return Ok(Some(pane.session.clone()));
+    }
+    Ok(store::list()?
+        .into_iter()
+        .find(|record| {
+            record.adopted_from.is_some()
+                && record.ended_at.is_none()
+                && record.pane.as_deref() == Some(pane.id.as_str())
+        })
+        .map(|record| record.name))
+}
+
+/// Panes running Claude Code that are not ccx sessions yet, on ccx's tmux server and
+/// the user's default one
+pub fn discover() -> Result<Vec<tmux::Pane>> {
+    let processes = processes()?;
+    let mut found = Vec::new();
+    for pane in tmux::list_panes()? {
+        if runs_claude(pane.pid, &processes) && owning_session(&pane)?.is_none() {
+            found.push(pane);
+        }
+    }
+    Ok(found)
+}
+
+/// Register the pane a tmux target refers to as a ccx session, so it can be listed,
+/// captured, sent to and stopped like any other. ccx records the pane's id and leaves
+/// the pane and the tmux session around it as they are. Returns the new session name.
+pub fn adopt(target: &str, label: Option<String>) -> Result<String> {
+    let pane = tmux::find_pane(target)?;
+    if pane.other_server {
+        anyhow::bail!(
+            "Pane {} is on your default tmux server, but ccx runs its sessions on a \
+             dedicated server ([tmux] socket in ~/.ccx/config.toml) and can only adopt \
+             panes there",
+            pane.target
+        );
+    }
+    if let Some(session) = owning_session(&pane)? {
+        anyhow::bail!(
+            "Pane {} already belongs to ccx session {}",
+            pane.target,
+            session
+        );
+    }
+    let session_name = tmux::generate_session_name();
+    if !runs_claude(pane.pid, &processes()?) {
+        session::notice(
+            &session_name,
+            &format!(
+                "Warning: no Claude Code process found in pane {}; adopting it anyway",
+                pane.target
+            ),
+        );
+    }
+    let record = store::SessionRecord {
+        name: session_name.clone(),
+        cwd: pane.cwd.clone(),
+        pane: Some(pane.id.clone()),
+        adopted_from: Some(pane.target.clone()),
+        created_at: store::now(),
+        label: label.or(Some(pane.session.clone())),
+        git_start: git::snapshot(Path::new(&pane.cwd)),
+        ..Default::default()
+    };
+    store::save(&record)?;
+    tmux::session_log(
+        &session_name,
+        &format!(
+            "Adopted pane {} ({}, cwd: {})",
+            pane.id, pane.target, pane.cwd
+        ),
+    );
+    Ok(session_name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_claude() {
+        assert!(is_claude("claude --model opus"));
+        assert!(is_claude("/home/me/.local/bin/claude"));
+        assert!(is_claude(
+            "node /usr/lib/node_modules/@anthropic-ai/claude-code/cli.js -c"
+        ));
+        assert!(is_claude("/bin/sh /usr/local/bin/claude fix it"));
+        assert!(!is_claude("vim claude.md"));
+        assert!(!is_claude("grep claude notes.txt"));
+        assert!(!is_claude("-bash"));
+    }
+
+    #[test]
+    fn test_runs_claude() {
+        let processes = parse_processes(
+            "  100     1 -bash
+              200   100 node /opt/@anthropic-ai/claude-code/cli.js
+              201   200 cargo test
+              300     1 zsh
+              301   300 vim src/main.rs",
+        );
+        assert_eq!(processes.len(), 5);
+        assert_eq!(
+            processes[1].args,
+            "node /opt/@anthropic-ai/claude-code/cli.js"
+        );
+        assert!(runs_claude(100, &processes));
+        assert!(runs_claude(200, &processes));
+        assert!(!runs_claude(300, &processes));
+        assert!(!runs_claude(999, &processes));
+    }
+}
@@ -0,0 +1,193 @@
+use crate::config::expand_tilde;
+use anyhow::{Context, Result};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::Path;
+
+/// Model aliases Claude Code accepts besides full model names
+const MODEL_ALIASES: &[&str] = &["default", "opus", "sonnet", "haiku", "opusplan"];
+
+/// Claude Code options chosen per session, passed as flags to `claude`
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AgentOptions {
+    /// Model alias (`opus`, `sonnet`, ...) or full name (`claude-sonnet-4-5`)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub model: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_turns: Option<u32>,
+    /// Text appended to Claude Code's system prompt
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub append_system_prompt: Option<String>,
+    /// Tool rules allowed without a prompt, e.g. `Read` or `Bash(cargo test*)`
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub allowed_tools: Vec<String>,
+    /// Absolute path of an MCP server config file
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub mcp_config: Option<String>,
+}
+
+impl AgentOptions {
+    pub fn is_empty(&self) -> bool {
+        *self == AgentOptions::default()
+    }
+
+    /// Check the options before a session is created, so a typo fails `ccx start`
+    /// instead of the launched agent
+    pub fn validate(&self) -> Result<()> {
+        if let Some(model) = &self.model
+            && !is_valid_model(model)
+        {
+            anyhow::bail!(
+                "Invalid model '{}' (use {} or a model id like claude-sonnet-4-5)",
+                model,
+                MODEL_ALIASES.join(", ")
+            );
+        }
+        if self.max_turns == Some(0) {
+            anyhow::bail!("max_turns must be at least 1");
+        }
+        let tool_rule = Regex::new(r"^[A-Za-z][A-Za-z0-9_-]*(\(.+\))?$").unwrap();
+        if let Some(rule) = self.allowed_tools.iter().find(|r| !tool_rule.is_match(r)) {
+            anyhow::bail!(
+                "Invalid allowed tool '{}' (expected Tool or Tool(pattern))",
+                rule
+            );
+        }
+        if let Some(path) = &self.mcp_config {
+            check_mcp_config(Path::new(path))?;
+        }
+        Ok(())
+    }
+
+    /// Flags for `claude`. `--allowed-tools` takes each rule as its own argument, as a
+    /// rule may contain commas, so it comes last.
+    pub fn args(&self) -> Vec<String> {
+        let mut args = Vec::new();
+        if let Some(model) = &self.model {
+            args.extend(["--model".to_string(), model.clone()]);
+        }
+        if let Some(max_turns) = self.max_turns {
+            args.extend(["--max-turns".to_string(), max_turns.to_string()]);
+        }
+        if let Some(prompt) = &self.append_system_prompt {
+            args.extend(["--append-system-prompt".to_string(), prompt.clone()]);
+        }
+        if let Some(path) = &self.mcp_config {
+            args.extend(["--mcp-config".to_string(), path.clone()]);
+        }
+        if !self.allowed_tools.is_empty() {
+            args.push("--allowed-tools".to_string());
+            args.extend(self.allowed_tools.iter().cloned());
+        }
+        args
+    }
+}
+
+/// A model alias, or a model id made of characters that are safe on a command line:
+/// `claude-sonnet-4-5`, Bedrock's `us.anthropic.claude-…`, Vertex's `claude-…@20241022`
+fn is_valid_model(model: &str) -> bool {
+    MODEL_ALIASES.contains(&model)
+        || (model.starts_with(|c: char| c.is_ascii_alphanumeric())
+            && model.chars().all(|c| {
+                c.is_ascii_alphanumeric()
+                    || matches!(c, '-' | '.' | '_' | ':' | '@' | '/' | '[' | ']')
+            }))
+}
+
+/// Read a system prompt file given to `--append-system-prompt-file`
+pub fn read_prompt_file(path: &str) -> Result<String> {
+    let path = expand_tilde(path);
+    let text =
+        fs::read_to_string(&path).with_context(|| format!("Failed to read {}", path.display()))?;
+    if text.trim().is_empty() {
+        anyhow::bail!("System prompt file {} is empty", path.display());
+    }
+    Ok(text.trim_end().to_string())
+}
+
+/// Absolute path of an MCP config file; the agent runs in another directory
+pub fn resolve_mcp_config(path: &str) -> Result<String> {
+    let path = expand_tilde(path);
+    let path = fs::canonicalize(&path)
+        .with_context(|| format!("MCP config {} not found", path.display()))?;
+    Ok(path.to_string_lossy().to_string())
+}
+
+/// An MCP config must be JSON with an `mcpServers` object
+fn check_mcp_config(path: &Path) -> Result<()> {
+    let content =
+        fs::read_to_string(path).with_context(|| format!("Failed to read {}", path.display()))?;
+    let config: serde_json::Value = serde_json::from_str(&content)
+        .with_context(|| format!("MCP config {} is not valid JSON", path.display()))?;
+    if !config.get("mcpServers").is_some_and(|s| s.is_object()) {
+        anyhow::bail!("MCP config {} has no mcpServers object", path.display());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_validate_model() {
+        assert!(is_valid_model("opus"));
+        assert!(is_valid_model("claude-sonnet-4-5-20250929"));
+        assert!(is_valid_model("claude-sonnet-4[1m]"));
+        assert!(is_valid_model(
+            "us.anthropic.claude-sonnet-4-5-20250929-v1:0"
+        ));
+        assert!(is_valid_model("claude-3-5-sonnet@20241022"));
+        assert!(!is_valid_model(""));
+        assert!(!is_valid_model("-p"));
+        assert!(!is_valid_model("claude opus"));
+        assert!(!is_valid_model("claude-opus; rm -rf"));
+        assert!(!is_valid_model("$(whoami)"));
+    }
+
+    #[test]
+    fn test_validate_allowed_tools() {
+        let mut options = AgentOptions {
+            allowed_tools: vec!["Read".to_string(), "Bash(cargo test:*)".to_string()],
+            ..Default::default()
+        };
+        options.validate().unwrap();
+        options.allowed_tools.push("Bash(".to_string());
+        assert!(options.validate().is_err());
+    }
+
+    #[test]
+    fn test_args() {
+        let options = AgentOptions {
+            model: Some("opus".to_string()),
+            max_turns: Some(20),
+            allowed_tools: vec!["Read".to_string(), "Bash(echo a,b)".to_string()],
+            ..Default::default()
+        };
+        assert_eq!(
+            options.args(),
+            [
+                "--model",
+                "opus",
+                "--max-turns",
+                "20",
+                "--allowed-tools",
+                "Read",
+                "Bash(echo a,b)"
+            ]
+        );
+        assert!(AgentOptions::default().args().is_empty());
+    }
+
+    #[test]
+    fn test_check_mcp_config() {
+        let path = std::env::temp_dir().join(format!("ccx-test-mcp-{}.json", std::process::id()));
+        fs::write(&path, r#"{"mcpServers": {"db": {"command": "db-mcp"}}}"#).unwrap();
+        assert!(check_mcp_config(&path).is_ok());
+        fs::write(&path, r#"{"servers": {}}"#).unwrap();
+        assert!(check_mcp_config(&path).is_err());
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,762 @@
+use crate::SessionManager;
+use crate::session::{self, Launch, Phase};
+use crate::{config, store, template, tmux};
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// One task (or pipeline step) in a manifest
+#[derive(Debug, Clone, Deserialize)]
+#[serde(try_from = "RawTask")]
+pub struct Task {
+    /// Unique name within the batch, used for `depends_on` and as the session label
+    pub name: String,
+    /// Tasks whose sessions must be done before this one starts
+    #[serde(default)]
+    pub depends_on: Vec<String>,
+    /// Run in the same directory (or worktree) as this dependency's session
+    #[serde(default)]
+    pub cwd_from: Option<String>,
+    /// Start in a new git worktree
+    #[serde(default)]
+    pub worktree: bool,
+    pub launch: Launch,
+}
+
+/// A task as written: its own keys, with the rest making up its [`Launch`]. serde
+/// ignores `deny_unknown_fields` on a flattened struct, so the rest is collected and
+/// deserialized separately, which rejects misspelled keys.
+#[derive(Deserialize)]
+struct RawTask {
+    name: String,
+    #[serde(default)]
+    depends_on: Vec<String>,
+    #[serde(default)]
+    cwd_from: Option<String>,
+    #[serde(default)]
+    worktree: bool,
+    #[serde(flatten)]
+    launch: serde_json::Map<String, serde_json::Value>,
+}
+
+impl TryFrom<RawTask> for Task {
+    type Error = String;
+
+    fn try_from(raw: RawTask) -> Result<Task, String> {
+        let launch = serde_json::from_value(serde_json::Value::Object(raw.launch))
+            .map_err(|e| format!("task '{}': {}", raw.name, e))?;
+        Ok(Task {
+            name: raw.name,
+            depends_on: raw.depends_on,
+            cwd_from: raw.cwd_from,
+            worktree: raw.worktree,
+            launch,
+        })
+    }
+}
+
+/// A manifest: `[[task]]` (or `[[step]]`) tables in TOML, or one task object per line
+/// in JSONL
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Manifest {
+    /// Maximum number of the batch's sessions running at once (default: unlimited)
+    #[serde(default)]
+    pub max_concurrent: Option<usize>,
+    /// Once any task fails, skip the tasks not yet started and stop the running ones
+    /// (default: true for pipelines)
+    #[serde(default)]
+    pub stop_on_failure: Option<bool>,
+    #[serde(default, rename = "task", alias = "step")]
+    pub tasks: Vec<Task>,
+}
+
+impl Manifest {
+    /// Read a manifest, choosing the format from the file extension
+    pub fn load(path: &Path) -> Result<Manifest> {
+        let content = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read {}", path.display()))?;
+        let manifest = if path.extension().is_some_and(|ext| ext == "jsonl") {
+            parse_jsonl(&content)
+        } else {
+            toml::from_str(&content).map_err(anyhow::Error::from)
+        }
+        .with_context(|| format!("Failed to parse {}", path.display()))?;
+        manifest.validate()?;
+        Ok(manifest)
+    }
+
+    /// Check task names are unique, dependencies exist, and there are no cycles
+    pub fn validate(&self) -> Result<()> {
+        if self.tasks.is_empty() {
+            anyhow::bail!("Manifest has no tasks");
+        }
+        let mut names = HashSet::new();
+        for task in &self.tasks {
+            if !names.insert(task.name.as_str()) {
+                anyhow::bail!("Duplicate task name '{}'", task.name);
+            }
+            let launch = &task.launch;
+            if launch.prompt.is_none() && launch.template.is_none() && launch.profile.is_none() {
+                anyhow::bail!("Task '{}' needs a prompt or template", task.name);
+            }
+        }
+        for task in &self.tasks {
+            for dep in &task.depends_on {
+                if !names.contains(dep.as_str()) {
+                    anyhow::bail!("Task '{}' depends on unknown task '{}'", task.name, dep);
+                }
+            }
+            if let Some(from) = &task.cwd_from
+                && !task.depends_on.contains(from)
+            {
+                anyhow::bail!(
+                    "Task '{}' takes its cwd from '{}' but does not depend on it",
+                    task.name,
+                    from
+                );
+            }
+        }
+
+        // Repeatedly remove tasks whose dependencies are all resolved
+        let mut resolved: HashSet<&str> = HashSet::new();
+        while resolved.len() < self.tasks.len() {
+            let ready: Vec<&str> = self
+                .tasks
+                .iter()
+                .filter(|t| !resolved.contains(t.name.as_str()))
+                .filter(|t| t.depends_on.iter().all(|d| resolved.contains(d.as_str())))
+                .map(|t| t.name.as_str())
+                .collect();
+            if ready.is_empty() {
+                anyhow::bail!("Task dependencies contain a cycle");
+            }
+            resolved.extend(ready);
+        }
+
+        Ok(())
+    }
+}
+
+fn parse_jsonl(content: &str) -> Result<Manifest> {
+    let mut tasks = Vec::new();
+    for (i, line) in content.lines().enumerate() {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let task = serde_json::from_str(line).with_context(|| format!("Line {}", i + 1))?;
+        tasks.push(task);
+    }
+    Ok(Manifest {
+        tasks,
+        ..Default::default()
+    })
+}
+
+/// Where a batch task is
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case", tag = "state")]
+pub enum TaskState {
+    /// Waiting for dependencies or a free slot
+    Pending,
+    /// Session was started
+    Started { session: String },
+    /// Not started because a dependency failed
+    Skipped { reason: String },
+    /// Session was stopped while running because another task failed
+    Stopped { session: String, reason: String },
+    /// Starting the session failed
+    Error { message: String },
+}
+
+/// Progress of a task, stored in the batch record
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TaskRecord {
+    pub name: String,
+    pub cwd: Option<String>,
+    #[serde(flatten)]
+    pub state: TaskState,
+}
+
+/// A launched batch, stored at ~/.ccx/batches/<batch-id>.json
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BatchRecord {
+    pub id: String,
+    pub manifest: String,
+    pub created_at: u64,
+    pub tasks: Vec<TaskRecord>,
+}
+
+fn batches_dir() -> Result<PathBuf> {
+    Ok(config::ccx_home()?.join("batches"))
+}
+
+/// Skip a failed batch's pending tasks and stop its running sessions with `stop`.
+/// Returns how many sessions were stopped.
+fn halt(
+    tasks: &mut [TaskRecord],
+    phases: &[Option<Phase>],
+    reason: &str,
+    stop: &mut dyn FnMut(&str) -> Result<()>,
+    report: &mut dyn FnMut(&str),
+) -> usize {
+    let mut stopped = 0;
+    for (task, phase) in tasks.iter_mut().zip(phases) {
+        match &task.state {
+            TaskState::Pending => {
+                report(&format!("Skipped {}: {}", task.name, reason));
+                task.state = TaskState::Skipped {
+                    reason: reason.to_string(),
+                };
+            }
+            TaskState::Started { session } if *phase == Some(Phase::Running) => {
+                // A session that ended in the meantime keeps its own outcome
+                if let Err(e) = stop(session) {
+                    report(&format!("Failed to stop {}: {:#}", task.name, e));
+                    continue;
+                }
+                report(&format!("Stopped {}: {}", task.name, reason));
+                task.state = TaskState::Stopped {
+                    session: session.clone(),
+                    reason: reason.to_string(),
+                };
+                stopped += 1;
+            }
+            _ => {}
+        }
+    }
+    stopped
+}
+
+impl BatchRecord {
+    pub fn load(id: &str) -> Result<BatchRecord> {
+        let path = batches_dir()?.join(format!("{}.json", id));
+        let content = fs::read_to_string(&path)
+            .with_context(|| format!("No batch found with id '{}'", id))?;
+        serde_json::from_str(&content)
+            .with_context(|| format!("Failed to parse {}", path.display()))
+    }
+
+    fn save(&self) -> Result<()> {
+        let dir = batches_dir()?;
+        fs::create_dir_all(&dir).with_context(|| format!("Failed to create {}", dir.display()))?;
+        let path = dir.join(format!("{}.json", self.id));
+        fs::write(&path, serde_json::to_string_pretty(self)?)
+            .with_context(|| format!("Failed to write {}", path.display()))
+    }
+
+    /// All batch records, oldest first
+    pub fn list() -> Result<Vec<BatchRecord>> {
+        let dir = batches_dir()?;
+        if !dir.exists() {
+            return Ok(Vec::new());
+        }
+        let mut batches: Vec<BatchRecord> = fs::read_dir(&dir)?
+            .filter_map(|e| e.ok())
+            .filter_map(|e| fs::read_to_string(e.path()).ok())
+            .filter_map(|content| serde_json::from_str(&content).ok())
+            .collect();
+        batches.sort_by_key(|b| b.created_at);
+        Ok(batches)
+    }
+}
+
+/// Current status of a task for display: pending/skipped/error, or its session's phase
+pub fn task_status(task: &TaskRecord) -> String {
+    match &task.state {
+        TaskState::Pending => "pending".to_string(),
+        TaskState::Started { session } => session::phase(session).to_string(),
+        TaskState::Skipped { .. } => "skipped".to_string(),
+        TaskState::Stopped { .. } => "stopped".to_string(),
+        TaskState::Error { .. } => "error".to_string(),
+    }
+}
+
+/// Which command launched a manifest
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Kind {
+    /// `ccx batch run`: independent tasks, dependents of a failed task are skipped
+    Batch,
+    /// `ccx pipeline run`: waits for every step and stops at the first failure
+    Pipeline,
+}
+
+impl Kind {
+    fn id_prefix(self) -> &'static str {
+        match self {
+            Kind::Batch => "batch",
+            Kind::Pipeline => "pipeline",
+        }
+    }
+}
+
+/// Template variables describing a finished task, for downstream prompts:
+/// `steps.<name>.session`, `.cwd`, `.branch`, `.commits` and `.final_message`
+fn output_vars(task_name: &str, session: &str) -> Vec<(String, String)> {
+    // The session's supervisor may not have recorded its outcome yet
+    session::record_completion(session);
+    match store::load(session) {
+        Ok(Some(record)) => record_vars(task_name, session, record),
+        _ => Vec::new(),
+    }
+}
+
+fn record_vars(
+    task_name: &str,
+    session: &str,
+    record: store::SessionRecord,
+) -> Vec<(String, String)> {
+    let key = |field: &str| format!("steps.{}.{}", task_name, field);
+    let mut vars = vec![
+        (key("session"), session.to_string()),
+        (key("cwd"), record.cwd.clone()),
+        (
+            key("final_message"),
+            record.final_message.unwrap_or_default(),
+        ),
+    ];
+    if let Some(branch) = record.git_start.and_then(|snap| snap.branch) {
+        vars.push((key("branch"), branch));
+    }
+    if let Some(changes) = record.git_end {
+        vars.push((key("commits"), changes.commits.join("\n")));
+    }
+    vars
+}
+
+/// Launch every task in a manifest, starting each once its dependencies are done and
+/// a concurrency slot is free. Batches return once every task has been started or
+/// skipped; pipelines wait for every session to finish and stop at the first failure.
+pub fn run(
+    manifest_path: &Path,
+    kind: Kind,
+    max_concurrent: Option<usize>,
+    report: &mut dyn FnMut(&str),
+) -> Result<BatchRecord> {
+    use std::thread;
+    use std::time::Duration;
+
+    let manifest = Manifest::load(manifest_path)?;
+    let max_concurrent = max_concurrent.or(manifest.max_concurrent);
+    let stop_on_failure = manifest.stop_on_failure.unwrap_or(kind == Kind::Pipeline);
+    let wait = kind == Kind::Pipeline;
+    let manifest_path = manifest_path
+        .canonicalize()
+        .unwrap_or_else(|_| manifest_path.to_path_buf());
+
+    // Relative task directories are resolved against the manifest's directory
+    let base_dir = manifest_path.parent().map(Path::to_path_buf);
+    let resolve_dir = |cwd: &Option<String>| -> Option<String> {
+        let cwd = cwd.as_ref()?;
+        let path = config::expand_tilde(cwd);
+        match (&base_dir, path.is_relative()) {
+            (Some(base), true) => Some(base.join(path).to_string_lossy().to_string()),
+            _ => Some(path.to_string_lossy().to_string()),
+        }
+    };
+
+    let mut batch = BatchRecord {
+        id: format!("{}-{:08x}", kind.id_prefix(), tmux::rand_id()),
+        manifest: manifest_path.to_string_lossy().to_string(),
+        created_at: store::now(),
+        tasks: manifest
+            .tasks
+            .iter()
+            .map(|t| TaskRecord {
+                name: t.name.clone(),
+                cwd: resolve_dir(&t.launch.cwd),
+                state: TaskState::Pending,
+            })
+            .collect(),
+    };
+    batch.save()?;
+    report(&format!(
+        "{}: {}",
+        if wait { "Pipeline" } else { "Batch" },
+        batch.id
+    ));
+
+    let index_of = |name: &str| {
+        manifest
+            .tasks
+            .iter()
+            .position(|t| t.name == name)
+            .expect("validated task name")
+    };
+
+    loop {
+        let phases: Vec<Option<Phase>> = batch
+            .tasks
+            .iter()
+            .map(|t| match &t.state {
+                TaskState::Started { session } => Some(session::phase(session)),
+                _ => None,
+            })
+            .collect();
+        let mut running = phases
+            .iter()
+            .filter(|p| **p == Some(Phase::Running))
+            .count();
+
+        let failed = batch.tasks.iter().zip(&phases).find(|(t, phase)| {
+            **phase == Some(Phase::Failed) || matches!(t.state, TaskState::Error { .. })
+        });
+        if stop_on_failure && let Some((failed, _)) = failed {
+            let reason = format!("stopped after '{}' failed", failed.name);
+            let manager = SessionManager::new();
+            running -= halt(
+                &mut batch.tasks,
+                &phases,
+                &reason,
+                &mut |session| manager.stop(session),
+                report,
+            );
+        }
+
+        for (i, task) in manifest.tasks.iter().enumerate() {
+            if batch.tasks[i].state != TaskState::Pending {
+                continue;
+            }
+
+            let mut blocked = false;
+            let mut failed_dep = None;
+            for dep in &task.depends_on {
+                let j = index_of(dep);
+                match (&batch.tasks[j].state, phases[j]) {
+                    (TaskState::Started { .. }, Some(Phase::Done)) => {}
+                    (TaskState::Started { .. }, Some(Phase::Failed))
+                    | (TaskState::Skipped { .. }, _)
+                    | (TaskState::Stopped { .. }, _)
+                    | (TaskState::Error { .. }, _) => failed_dep = Some(dep.clone()),
+                    _ => blocked = true,
+                }
+            }
+
+            if let Some(dep) = failed_dep {
+                let reason = format!("dependency '{}' did not complete", dep);
+                report(&format!("Skipped {}: {}", task.name, reason));
+                batch.tasks[i].state = TaskState::Skipped { reason };
+                continue;
+            }
+            if blocked || max_concurrent.is_some_and(|max| running >= max) {
+                continue;
+            }
+
+            // Outputs of finished tasks are available to this task's prompt and vars
+            let mut outputs = HashMap::new();
+            for (j, record) in batch.tasks.iter().enumerate() {
+                if let (TaskState::Started { session }, Some(Phase::Done)) =
+                    (&record.state, phases[j])
+                {
+                    outputs.extend(output_vars(&record.name, session));
+                }
+            }
+
+            let mut launch = task.launch.clone();
+            launch.prompt = launch.prompt.map(|p| template::render(&p, &outputs));
+            for value in launch.vars.values_mut() {
+                *value = template::render(value, &outputs);
+            }
+            for (key, value) in &outputs {
+                launch
+                    .vars
+                    .entry(key.clone())
+                    .or_insert_with(|| value.clone());
+            }
+            if let Some(from) = &task.cwd_from
+                && let TaskState::Started { session } = &batch.tasks[index_of(from)].state
+            {
+                batch.tasks[i].cwd = store::load(session)?.map(|record| record.cwd);
+            }
+            launch.cwd = batch.tasks[i].cwd.clone();
+
+            let started = launch.prepare().and_then(|mut opts| {
+                opts.label = Some(task.name.clone());
+                opts.batch = Some(batch.id.clone());
+                if task.worktree {
+                    opts.worktree_branch = Some(format!("ccx/{}-{}", batch.id, task.name));
+                }
+                session::start(opts)
+            });
+            batch.tasks[i].state = match started {
+                Ok(session) => {
+                    report(&format!("Started {}: {}", task.name, session));
+                    running += 1;
+                    TaskState::Started { session }
+                }
+                Err(e) => {
+                    report(&format!("Failed to start {}: {:#}", task.name, e));
+                    TaskState::Error {
+                        message: format!("{:#}", e),
+                    }
+                }
+            };
+        }
+
+        batch.save()?;
+        let all_launched = batch.tasks.iter().all(|t| t.state != TaskState::Pending);
+        if all_launched && (!wait || running == 0) {
+            return Ok(batch);
+        }
+        thread::sleep(Duration::from_secs(2));
+    }
+}
+
+impl BatchRecord {
+    /// Whether any task errored, was skipped, or has a failed session
+    pub fn has_failures(&self) -> bool {
+        self.tasks.iter().any(|t| match &t.state {
+            TaskState::Started { session } => session::phase(session) == Phase::Failed,
+            TaskState::Pending => false,
+            TaskState::Skipped { .. } | TaskState::Stopped { .. } | TaskState::Error { .. } => true,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::process::Command;
+
+    fn git(dir: &Path, args: &[&str]) {
+        let status = Command::new("git")
+            .arg("-C")
+            .arg(dir)
+            .args(["-c", "user.name=t", "-c", "user.email=t@t"])
+            .args(args)
+            .output()
+            .unwrap()
+            .status;
+        assert!(status.success(), "git {:?}", args);
+    }
+
+    #[test]
+    fn test_halt() {
+        let task = |name: &str, state: TaskState| TaskRecord {
+            name: name.to_string(),
+            cwd: None,
+            state,
+        };
+        let started = |session: &str| TaskState::Started {
+            session: session.to_string(),
+        };
+        let mut tasks = vec![
+            task("lint", started("ccx-1")),
+            task("test", started("ccx-2")),
+            task("docs", started("ccx-3")),
+            task("ended", started("ccx-4")),
+            task("release", TaskState::Pending),
+        ];
+        let phases = [
+            Some(Phase::Failed),
+            Some(Phase::Running),
+            Some(Phase::Done),
+            Some(Phase::Running),
+            None,
+        ];
+
+        let mut stop_calls = Vec::new();
+        let mut stop = |session: &str| {
+            stop_calls.push(session.to_string());
+            match session {
+                "ccx-4" => anyhow::bail!("Session '{}' does not exist", session),
+                _ => Ok(()),
+            }
+        };
+        let reason = "stopped after 'lint' failed";
+        let stopped = halt(&mut tasks, &phases, reason, &mut stop, &mut |_| {});
+
+        assert_eq!(stopped, 1);
+        assert_eq!(stop_calls, vec!["ccx-2", "ccx-4"]);
+        assert_eq!(tasks[0].state, started("ccx-1"));
+        assert_eq!(
+            tasks[1].state,
+            TaskState::Stopped {
+                session: "ccx-2".to_string(),
+                reason: reason.to_string(),
+            }
+        );
+        assert_eq!(tasks[2].state, started("ccx-3"));
+        assert_eq!(tasks[3].state, started("ccx-4"));
+        assert_eq!(
+            tasks[4].state,
+            TaskState::Skipped {
+                reason: reason.to_string(),
+            }
+        );
+    }
+
+    #[test]
+    fn test_outputs_of_completed_step() {
+        let dir = std::env::temp_dir().join(format!("ccx-test-outputs-{}", tmux::rand_id()));
+        fs::create_dir_all(&dir).unwrap();
+        git(&dir, &["init", "-q", "-b", "main"]);
+        git(&dir, &["commit", "-q", "--allow-empty", "-m", "initial"]);
+
+        // The step's session starts, commits, and completes
+        let mut record = store::SessionRecord {
+            name: "ccx-1".to_string(),
+            cwd: dir.to_string_lossy().to_string(),
+            git_start: crate::git::snapshot(&dir),
+            ..Default::default()
+        };
+        git(
+            &dir,
+            &["commit", "-q", "--allow-empty", "-m", "fix the parser"],
+        );
+        session::complete(&mut record).unwrap();
+
+        let outputs: HashMap<String, String> =
+            record_vars("fix", "ccx-1", record).into_iter().collect();
+        let prompt = template::render(
+            "Review {{steps.fix.commits}} on {{steps.fix.branch}} by {{steps.fix.session}}",
+            &outputs,
+        );
+        let _ = fs::remove_dir_all(&dir);
+        assert!(prompt.starts_with("Review "), "{}", prompt);
+        assert!(prompt.contains("fix the parser"), "{}", prompt);
+        assert!(prompt.ends_with(" on main by ccx-1"), "{}", prompt);
+    }
+
+    #[test]
+    fn test_misspelled_key() {
+        let err = toml::from_str::<Manifest>(
+            r#"
+            [[task]]
+            name = "a"
+            promt = "hi"
+            "#,
+        )
+        .unwrap_err();
+        assert!(err.to_string().contains("promt"), "{}", err);
+
+        let err = parse_jsonl(r#"{"name": "a", "prompt": "hi", "modle": "opus"}"#).unwrap_err();
+        assert!(format!("{:#}", err).contains("modle"), "{:#}", err);
+    }
+
+    #[test]
+    fn test_parse_toml_manifest() {
+        let manifest: Manifest = toml::from_str(
+            r#"
+            max_concurrent = 2
+
+            [[task]]
+            name = "refactor"
+            prompt = "refactor the parser"
+            cwd = "~/src/app"
+            tags = ["parser"]
+            model = "opus"
+            allowed_tools = ["Read", "Bash(cargo test:*)"]
+
+            [[task]]
+            name = "tests"
+            template = "write-tests"
+            vars = { module = "parser" }
+            depends_on = ["refactor"]
+            "#,
+        )
+        .unwrap();
+        assert_eq!(manifest.max_concurrent, Some(2));
+        assert_eq!(manifest.tasks.len(), 2);
+        assert_eq!(manifest.tasks[1].depends_on, vec!["refactor"]);
+        assert_eq!(manifest.tasks[1].launch.vars["module"], "parser");
+        assert_eq!(manifest.tasks[0].launch.model.as_deref(), Some("opus"));
+        assert_eq!(manifest.tasks[0].launch.allowed_tools.len(), 2);
+        manifest.validate().unwrap();
+    }
+
+    #[test]
+    fn test_parse_pipeline_steps() {
+        let manifest: Manifest = toml::from_str(
+            r#"
+            [[step]]
+            name = "refactor"
+            prompt = "refactor the parser"
+            worktree = true
+
+            [[step]]
+            name = "tests"
+            prompt = "write tests for: {{steps.refactor.final_message}}"
+            depends_on = ["refactor"]
+            cwd_from = "refactor"
+            "#,
+        )
+        .unwrap();
+        assert_eq!(manifest.tasks.len(), 2);
+        assert!(manifest.tasks[0].worktree);
+        assert_eq!(manifest.tasks[1].cwd_from.as_deref(), Some("refactor"));
+        manifest.validate().unwrap();
+    }
+
+    #[test]
+    fn test_parse_jsonl_manifest() {
+        let manifest = parse_jsonl(
+            r#"{"name": "a", "prompt": "do a", "cwd": "/tmp/a"}
+
+{"name": "b", "prompt": "do b", "depends_on": ["a"]}"#,
+        )
+        .unwrap();
+        assert_eq!(manifest.tasks.len(), 2);
+        assert_eq!(manifest.tasks[0].launch.cwd.as_deref(), Some("/tmp/a"));
+        manifest.validate().unwrap();
+    }
+
+    fn task(name: &str, deps: &[&str]) -> Task {
+        Task {
+            name: name.to_string(),
+            depends_on: deps.iter().map(|d| d.to_string()).collect(),
+            cwd_from: None,
+            worktree: false,
+            launch: Launch {
+                prompt: Some("p".to_string()),
+                ..Default::default()
+            },
+        }
+    }
+
+    #[test]
+    fn test_validate_rejects_bad_manifests() {
+        let dup = Manifest {
+            tasks: vec![task("a", &[]), task("a", &[])],
+            ..Default::default()
+        };
+        assert!(dup.validate().is_err());
+
+        let unknown = Manifest {
+            tasks: vec![task("a", &["missing"])],
+            ..Default::default()
+        };
+        assert!(unknown.validate().is_err());
+
+        let cycle = Manifest {
+            tasks: vec![task("a", &["b"]), task("b", &["a"])],
+            ..Default::default()
+        };
+        assert!(cycle.validate().is_err());
+
+        let mut b = task("b", &[]);
+        b.cwd_from = Some("a".to_string());
+        let cwd_not_dep = Manifest {
+            tasks: vec![task("a", &[]), b],
+            ..Default::default()
+        };
+        assert!(cwd_not_dep.validate().is_err());
+    }
+
+    #[test]
+    fn test_task_state_serialization() {
+        let record = TaskRecord {
+            name: "a".to_string(),
+            cwd: None,
+            state: TaskState::Started {
+                session: "ccx-0000abcd".to_string(),
+            },
+        };
+        let json = serde_json::to_string(&record).unwrap();
+        assert!(json.contains(r#""state":"started""#));
+        let parsed: TaskRecord = serde_json::from_str(&json).unwrap();
+        assert_eq!(parsed.state, record.state);
+    }
+}
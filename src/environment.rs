@@ -0,0 +1,168 @@
+use anyhow::{Context, Result};
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+use std::process::Command;
+
+/// The value of an environment variable set for a session: literal text, or a secret
+/// read when the session starts so it never has to be written into config
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(untagged, deny_unknown_fields)]
+pub enum EnvValue {
+    Plain(String),
+    /// Contents of a file, e.g. `{ file = "~/.secrets/github-token" }`
+    File {
+        file: String,
+    },
+    /// Output of a command, e.g. `{ command = "pass show github/token" }`
+    Command {
+        command: String,
+    },
+}
+
+impl EnvValue {
+    /// Produce the value, reading files and running commands for secret references.
+    /// Errors name the variable but never include a secret's value.
+    pub fn resolve(&self, key: &str) -> Result<String> {
+        match self {
+            EnvValue::Plain(value) => Ok(value.clone()),
+            EnvValue::File { file } => {
+                let path = crate::config::expand_tilde(file);
+                let value = fs::read_to_string(&path).with_context(|| {
+                    format!("Failed to read {} for env var {}", path.display(), key)
+                })?;
+                Ok(value.trim_end_matches(['\n', '\r']).to_string())
+            }
+            EnvValue::Command { command } => {
+                let output = Command::new("sh")
+                    .args(["-c", command])
+                    .output()
+                    .with_context(|| format!("Failed to run command for env var {}", key))?;
+                if !output.status.success() {
+                    anyhow::bail!(
+                        "Command for env var {} failed: {}",
+                        key,
+                        String::from_utf8_lossy(&output.stderr).trim()
+                    );
+                }
+                Ok(String::from_utf8_lossy(&output.stdout)
+                    .trim_end_matches(['\n', '\r'])
+                    .to_string())
+            }
+        }
+    }
+}
+
+/// Parse a `KEY=VALUE` command-line variable
+pub fn parse_env(s: &str) -> Result<(String, String), String> {
+    match s.split_once('=') {
+        Some((key, value)) if is_valid_name(key) => Ok((key.to_string(), value.to_string())),
+        // The value may be a secret, so it is left out of the error
+        _ => Err("expected KEY=VALUE with a valid variable name".to_string()),
+    }
+}
+
+fn is_valid_name(key: &str) -> bool {
+    !key.is_empty()
+        && !key.starts_with(|c: char| c.is_ascii_digit())
+        && key.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// Read a dotenv-style file: `KEY=VALUE` lines with optional `export ` prefixes and
+/// quotes; blank lines and `#` comments are skipped
+pub fn read_env_file(path: &Path) -> Result<BTreeMap<String, String>> {
+    let content =
+        fs::read_to_string(path).with_context(|| format!("Failed to read {}", path.display()))?;
+    let mut vars = BTreeMap::new();
+    for (i, line) in content.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let line = line.strip_prefix("export ").unwrap_or(line);
+        // The line may hold a secret, so it is left out of the error
+        let (key, value) = parse_env(line)
+            .map_err(|_| anyhow::anyhow!("{}:{}: expected KEY=VALUE", path.display(), i + 1))?;
+        let value = value.trim();
+        let value = value
+            .strip_prefix('"')
+            .and_then(|v| v.strip_suffix('"'))
+            .or_else(|| value.strip_prefix('\'').and_then(|v| v.strip_suffix('\'')))
+            .unwrap_or(value);
+        vars.insert(key, value.to_string());
+    }
+    Ok(vars)
+}
+
+/// Resolve every variable, in key order
+pub fn resolve_all(env: &BTreeMap<String, EnvValue>) -> Result<Vec<(String, String)>> {
+    env.iter()
+        .map(|(key, value)| Ok((key.clone(), value.resolve(key)?)))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_env_values() {
+        let env: BTreeMap<String, EnvValue> = toml::from_str(
+            r#"
+            RUST_LOG = "debug"
+            GITHUB_TOKEN = { command = "pass show github/token" }
+            NPM_TOKEN = { file = "~/.secrets/npm" }
+            "#,
+        )
+        .unwrap();
+        assert_eq!(env["RUST_LOG"], EnvValue::Plain("debug".to_string()));
+        assert_eq!(
+            env["GITHUB_TOKEN"],
+            EnvValue::Command {
+                command: "pass show github/token".to_string()
+            }
+        );
+        assert_eq!(
+            env["NPM_TOKEN"],
+            EnvValue::File {
+                file: "~/.secrets/npm".to_string()
+            }
+        );
+    }
+
+    #[test]
+    fn test_resolve_command() {
+        let value = EnvValue::Command {
+            command: "printf 's3cret\\n'".to_string(),
+        };
+        assert_eq!(value.resolve("TOKEN").unwrap(), "s3cret");
+
+        let failing = EnvValue::Command {
+            command: "exit 1".to_string(),
+        };
+        assert!(failing.resolve("TOKEN").is_err());
+    }
+
+    #[test]
+    fn test_parse_env() {
+        assert_eq!(
+            parse_env("A_1=x=y"),
+            Ok(("A_1".to_string(), "x=y".to_string()))
+        );
+        assert!(parse_env("1A=x").is_err());
+        assert!(parse_env("A-B=x").is_err());
+        assert!(parse_env("novalue").is_err());
+    }
+
+    #[test]
+    fn test_read_env_file() {
+        let path = std::env::temp_dir().join(format!("ccx-test-{}.env", std::process::id()));
+        fs::write(&path, "# comment\nexport A=1\nB=\"two words\"\n\nC='x'\n").unwrap();
+        let vars = read_env_file(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(vars["A"], "1");
+        assert_eq!(vars["B"], "two words");
+        assert_eq!(vars["C"], "x");
+    }
+}
@@ -0,0 +1,222 @@
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::Path;
+use std::process::Command;
+use std::sync::atomic::{AtomicU32, Ordering};
+
+/// Hash of git's empty tree, used as the diff base for repos that had no commits
+const EMPTY_TREE: &str = "4b825dc642cb6eb9a060e54bf8d69288fbee4904";
+
+/// Git state of a working tree when a session started
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct GitSnapshot {
+    /// Top-level directory of the working tree
+    pub toplevel: String,
+    /// Commit hash of HEAD (None if the repo had no commits yet)
+    pub head: Option<String>,
+    /// Checked out branch (None when HEAD is detached)
+    pub branch: Option<String>,
+    /// Whether the working tree had uncommitted changes
+    pub dirty: bool,
+}
+
+/// What a session changed relative to its starting snapshot
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct GitChanges {
+    /// Commit hash of HEAD when the session completed
+    pub head: Option<String>,
+    /// Commits made since the start, oldest first, in `--oneline` form
+    pub commits: Vec<String>,
+    /// `git diff --stat` of the working tree, new files included, against the
+    /// starting HEAD
+    pub diff_stat: String,
+    /// Whether the working tree had uncommitted changes
+    pub dirty: bool,
+}
+
+/// Run git in a directory and return its trimmed stdout
+fn git(dir: &Path, args: &[&str]) -> Result<String> {
+    run(Command::new("git").arg("-C").arg(dir), args)
+}
+
+/// Run a git command with `args`, returning its trimmed stdout
+fn run(command: &mut Command, args: &[&str]) -> Result<String> {
+    let output = command
+        .args(args)
+        .output()
+        .context("Failed to execute git")?;
+
+    if !output.status.success() {
+        anyhow::bail!(
+            "git {} failed: {}",
+            args.join(" "),
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+
+    Ok(String::from_utf8_lossy(&output.stdout)
+        .trim_end()
+        .to_string())
+}
+
+/// Resolve the top-level directory of the working tree containing `dir`
+pub fn toplevel(dir: &Path) -> Option<String> {
+    git(dir, &["rev-parse", "--show-toplevel"]).ok()
+}
+
+/// Absolute path of the repository's shared git directory (the main `.git`, even
+/// from a linked worktree)
+pub fn common_dir(dir: &Path) -> Option<String> {
+    git(
+        dir,
+        &["rev-parse", "--path-format=absolute", "--git-common-dir"],
+    )
+    .ok()
+}
+
+/// Capture HEAD, branch and dirty state for the repo containing `dir`.
+/// Returns None if `dir` is not inside a git working tree.
+pub fn snapshot(dir: &Path) -> Option<GitSnapshot> {
+    let toplevel = toplevel(dir)?;
+    let head = git(dir, &["rev-parse", "--verify", "-q", "HEAD"]).ok();
+    let branch = git(dir, &["symbolic-ref", "--short", "-q", "HEAD"]).ok();
+    let dirty = is_dirty(dir);
+
+    Some(GitSnapshot {
+        toplevel,
+        head,
+        branch,
+        dirty,
+    })
+}
+
+fn is_dirty(dir: &Path) -> bool {
+    git(dir, &["status", "--porcelain"])
+        .map(|s| !s.is_empty())
+        .unwrap_or(false)
+}
+
+/// The revision to diff against for a starting snapshot
+fn base_rev(start: &GitSnapshot) -> &str {
+    start.head.as_deref().unwrap_or(EMPTY_TREE)
+}
+
+/// Collect the commits and diff stat produced since `start`
+pub fn changes_since(start: &GitSnapshot) -> Result<GitChanges> {
+    let dir = Path::new(&start.toplevel);
+    let head = git(dir, &["rev-parse", "--verify", "-q", "HEAD"]).ok();
+
+    let commits = match (&start.head, &head) {
+        (_, None) => Vec::new(),
+        (Some(from), Some(_)) => log_oneline(dir, &format!("{}..HEAD", from))?,
+        (None, Some(_)) => log_oneline(dir, "HEAD")?,
+    };
+    let diff_stat = diff_since(start, true)?;
+
+    Ok(GitChanges {
+        head,
+        commits,
+        diff_stat,
+        dirty: is_dirty(dir),
+    })
+}
+
+fn log_oneline(dir: &Path, range: &str) -> Result<Vec<String>> {
+    let out = git(dir, &["log", "--oneline", "--reverse", range])?;
+    Ok(out.lines().map(str::to_string).collect())
+}
+
+/// Diff of the working tree against the starting HEAD (committed and uncommitted
+/// changes, including new files not yet added)
+pub fn diff_since(start: &GitSnapshot, stat: bool) -> Result<String> {
+    static TEMP_INDEXES: AtomicU32 = AtomicU32::new(0);
+
+    let dir = Path::new(&start.toplevel);
+    let mut args = vec!["diff"];
+    if stat {
+        args.push("--stat");
+    }
+    args.push(base_rev(start));
+
+    // Untracked files are added intent-to-add to a copy of the index, so they show
+    // up in the diff while the repository's own index is left alone
+    let index = git(
+        dir,
+        &["rev-parse", "--path-format=absolute", "--git-path", "index"],
+    )?;
+    let temp = std::env::temp_dir().join(format!(
+        "ccx-index-{}-{}",
+        std::process::id(),
+        TEMP_INDEXES.fetch_add(1, Ordering::Relaxed)
+    ));
+    if Path::new(&index).exists() {
+        fs::copy(&index, &temp).with_context(|| format!("Failed to copy {}", index))?;
+    }
+    let with_temp_index = || {
+        let mut command = Command::new("git");
+        command.arg("-C").arg(dir).env("GIT_INDEX_FILE", &temp);
+        command
+    };
+    let diff = run(&mut with_temp_index(), &["add", "-N", "--", "."])
+        .and_then(|_| run(&mut with_temp_index(), &args));
+    let _ = fs::remove_file(&temp);
+    diff
+}
+
+/// Create a new worktree at `path` on a new branch starting from the current HEAD
+pub fn worktree_add(toplevel: &str, path: &Path, branch: &str) -> Result<()> {
+    let path = path.to_string_lossy();
+    git(
+        Path::new(toplevel),
+        &["worktree", "add", "-q", "-b", branch, &path],
+    )?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_base_rev() {
+        let mut snap = GitSnapshot {
+            toplevel: "/tmp/repo".to_string(),
+            head: Some("abc123".to_string()),
+            branch: Some("main".to_string()),
+            dirty: false,
+        };
+        assert_eq!(base_rev(&snap), "abc123");
+
+        snap.head = None;
+        assert_eq!(base_rev(&snap), EMPTY_TREE);
+    }
+
+    #[test]
+    fn test_snapshot_outside_repo() {
+        assert!(snapshot(Path::new("/")).is_none());
+    }
+
+    #[test]
+    fn test_diff_includes_untracked_files() {
+        let dir = std::env::temp_dir().join(format!("ccx-test-diff-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let commit = ["-c", "user.name=t", "-c", "user.email=t@t", "commit", "-q"];
+        git(&dir, &["init", "-q"]).unwrap();
+        fs::write(dir.join("old.txt"), "old\n").unwrap();
+        git(&dir, &["add", "old.txt"]).unwrap();
+        git(&dir, &[&commit[..], &["-m", "initial"]].concat()).unwrap();
+        let start = snapshot(&dir).unwrap();
+
+        fs::write(dir.join("new.txt"), "new\n").unwrap();
+        let diff = diff_since(&start, false);
+        let stat = diff_since(&start, true);
+        let status = git(&dir, &["status", "--porcelain"]);
+        let _ = fs::remove_dir_all(&dir);
+
+        assert!(diff.unwrap().contains("+new"));
+        assert!(stat.unwrap().contains("new.txt"));
+        // The repository's index is untouched
+        assert_eq!(status.unwrap(), "?? new.txt");
+    }
+}
@@ -0,0 +1,198 @@
+use crate::{session, store, tmux};
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::fs;
+use std::io::{BufRead, BufReader, Write};
+use std::path::PathBuf;
+use std::process::{Command, Stdio};
+
+/// Flags that put claude in non-interactive print mode with one JSON event per line
+pub const PRINT_MODE_ARGS: &[&str] = &["-p", "--output-format", "stream-json", "--verbose"];
+
+/// How a headless run ended, from the process exit status and the final `result` event
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RunResult {
+    /// Exit status of claude; 128 + N if it was killed by signal N
+    pub exit_code: i32,
+    /// `success`, `error_max_turns`, `error_during_execution`, ...
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub subtype: Option<String>,
+    pub is_error: bool,
+    /// The final answer
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub result: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub num_turns: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub duration_ms: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub total_cost_usd: Option<f64>,
+    /// Claude Code's own session id, for `claude --resume`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub session_id: Option<String>,
+    /// Unix timestamp of when the run ended
+    pub ended_at: u64,
+}
+
+impl RunResult {
+    pub fn succeeded(&self) -> bool {
+        self.exit_code == 0 && !self.is_error
+    }
+}
+
+/// Where a headless session's streamed events are kept
+pub fn events_path(session: &str) -> Result<PathBuf> {
+    Ok(store::sessions_dir()?.join(format!("{}.events.jsonl", session)))
+}
+
+/// Fill in a result from a stream-json `result` event
+fn apply_result_event(result: &mut RunResult, event: &Value) {
+    let text = |key: &str| event.get(key).and_then(Value::as_str).map(str::to_string);
+    result.subtype = text("subtype");
+    result.result = text("result");
+    result.session_id = text("session_id");
+    result.is_error = event
+        .get("is_error")
+        .and_then(Value::as_bool)
+        .unwrap_or(false);
+    result.num_turns = event.get("num_turns").and_then(Value::as_u64);
+    result.duration_ms = event.get("duration_ms").and_then(Value::as_u64);
+    result.total_cost_usd = event.get("total_cost_usd").and_then(Value::as_f64);
+}
+
+/// A short human-readable line for the pane, for events worth showing
+fn summarize(event: &Value) -> Vec<String> {
+    match event.get("type").and_then(Value::as_str) {
+        Some("assistant") => event
+            .pointer("/message/content")
+            .and_then(Value::as_array)
+            .into_iter()
+            .flatten()
+            .filter_map(|block| match block.get("type").and_then(Value::as_str) {
+                Some("text") => block
+                    .get("text")
+                    .and_then(Value::as_str)
+                    .map(str::to_string),
+                Some("tool_use") => block
+                    .get("name")
+                    .and_then(Value::as_str)
+                    .map(|name| format!("⏺ {}", name)),
+                _ => None,
+            })
+            .collect(),
+        Some("result") => vec![format!(
+            "Result: {}",
+            event
+                .get("subtype")
+                .and_then(Value::as_str)
+                .unwrap_or("unknown")
+        )],
+        _ => Vec::new(),
+    }
+}
+
+/// Run `command` (claude in print mode) for a headless session: stream its events to
+/// the session store, then record its exit status and final result. Runs in the
+/// session's pane; returns claude's exit code.
+pub fn run(session: &str, command: &[String]) -> Result<i32> {
+    use std::os::unix::process::ExitStatusExt;
+
+    let (program, args) = command.split_first().context("No command to run")?;
+    tmux::set_working(session, "Headless");
+    let path = events_path(session)?;
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir).with_context(|| format!("Failed to create {}", dir.display()))?;
+    }
+    let mut events =
+        fs::File::create(&path).with_context(|| format!("Failed to write {}", path.display()))?;
+
+    let mut child = Command::new(program)
+        .args(args)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .spawn()
+        .with_context(|| format!("Failed to run {}", program))?;
+    let stdout = child
+        .stdout
+        .take()
+        .context("Failed to read claude's output")?;
+
+    let mut result = RunResult::default();
+    for line in BufReader::new(stdout).lines() {
+        let line = line?;
+        writeln!(events, "{}", line)?;
+        let Ok(event) = serde_json::from_str::<Value>(&line) else {
+            println!("{}", line);
+            continue;
+        };
+        if event.get("type").and_then(Value::as_str) == Some("result") {
+            apply_result_event(&mut result, &event);
+        }
+        for summary in summarize(&event) {
+            println!("{}", summary);
+        }
+    }
+
+    let status = child.wait()?;
+    result.exit_code = status
+        .code()
+        .or_else(|| status.signal().map(|signal| 128 + signal))
+        .unwrap_or(1);
+    result.ended_at = store::now();
+    tmux::session_log(
+        session,
+        &format!(
+            "Headless run exited with status {} ({})",
+            result.exit_code,
+            result.subtype.as_deref().unwrap_or("no result")
+        ),
+    );
+
+    let succeeded = result.succeeded();
+    let exit_code = result.exit_code;
+    let mut record = store::load(session)?.context("Session record not found")?;
+    record.result = Some(result);
+    store::save(&record)?;
+    if succeeded {
+        session::record_completion(session);
+    }
+    Ok(exit_code)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_apply_result_event() {
+        let event: Value = serde_json::from_str(
+            r#"{"type":"result","subtype":"success","is_error":false,"duration_ms":5120,
+            "num_turns":3,"result":"Fixed the parser.","session_id":"abc","total_cost_usd":0.04}"#,
+        )
+        .unwrap();
+        let mut result = RunResult::default();
+        apply_result_event(&mut result, &event);
+        assert_eq!(result.subtype.as_deref(), Some("success"));
+        assert_eq!(result.result.as_deref(), Some("Fixed the parser."));
+        assert_eq!(result.num_turns, Some(3));
+        assert!(result.succeeded());
+
+        result.exit_code = 1;
+        assert!(!result.succeeded());
+    }
+
+    #[test]
+    fn test_summarize() {
+        let event: Value = serde_json::from_str(
+            r#"{"type":"assistant","message":{"content":[
+            {"type":"text","text":"Running tests"},
+            {"type":"tool_use","name":"Bash","input":{"command":"cargo test"}}]}}"#,
+        )
+        .unwrap();
+        assert_eq!(summarize(&event), ["Running tests", "⏺ Bash"]);
+        let init: Value = serde_json::from_str(r#"{"type":"system","subtype":"init"}"#).unwrap();
+        assert!(summarize(&init).is_empty());
+    }
+}
@@ -0,0 +1,181 @@
+use anyhow::{Context, Result};
+use std::collections::HashMap;
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// Default prompt for `ccx issue`, rendered with the issue's `id`, `title` and `body`
+pub const DEFAULT_PROMPT: &str = "Work on itack issue {{id}}: {{title}}
+
+{{body}}
+
+When you have completed the work, commit your changes with a descriptive commit message.";
+
+/// An itack issue read from `.itack/<date>-issue-<id>.md`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Issue {
+    pub id: String,
+    pub title: String,
+    pub body: String,
+    pub path: PathBuf,
+}
+
+impl Issue {
+    /// Template variables describing this issue
+    pub fn vars(&self) -> HashMap<String, String> {
+        HashMap::from([
+            ("id".to_string(), self.id.clone()),
+            ("issue".to_string(), self.id.clone()),
+            ("title".to_string(), self.title.clone()),
+            ("body".to_string(), self.body.clone()),
+        ])
+    }
+}
+
+/// Find and parse the issue file for `id` under `<repo>/.itack/`
+pub fn find_issue(repo: &Path, id: &str) -> Result<Issue> {
+    let dir = repo.join(".itack");
+    let suffix = format!("-issue-{}.md", id);
+    let path = fs::read_dir(&dir)
+        .with_context(|| format!("No .itack directory in {}", repo.display()))?
+        .filter_map(|e| e.ok())
+        .map(|e| e.path())
+        .find(|p| {
+            p.file_name()
+                .is_some_and(|n| n.to_string_lossy().ends_with(&suffix))
+        })
+        .with_context(|| format!("No itack issue {} found in {}", id, dir.display()))?;
+
+    let content =
+        fs::read_to_string(&path).with_context(|| format!("Failed to read {}", path.display()))?;
+    let (title, body) = parse_issue(&content);
+    Ok(Issue {
+        id: id.to_string(),
+        title: title.unwrap_or_else(|| format!("Issue {}", id)),
+        body,
+        path,
+    })
+}
+
+/// Split an issue file into its title and body.
+/// The title comes from a `title:` field in YAML front matter or the first `# ` heading.
+fn parse_issue(content: &str) -> (Option<String>, String) {
+    let mut title = None;
+    let mut rest = content;
+
+    if let Some(after) = content.strip_prefix("---\n")
+        && let Some(end) = after.find("\n---")
+    {
+        for line in after[..end].lines() {
+            if let Some(value) = line.strip_prefix("title:") {
+                title = Some(value.trim().trim_matches(['"', '\'']).to_string());
+            }
+        }
+        rest = after[end + 4..].trim_start_matches(['-', '\n']);
+    }
+
+    let mut body_lines = Vec::new();
+    for line in rest.lines() {
+        if title.is_none()
+            && let Some(heading) = line.strip_prefix("# ")
+        {
+            title = Some(heading.trim().to_string());
+            continue;
+        }
+        body_lines.push(line);
+    }
+
+    (title, body_lines.join("\n").trim().to_string())
+}
+
+fn itack(repo: &Path, args: &[&str]) -> Result<()> {
+    let output = Command::new("itack")
+        .args(args)
+        .current_dir(repo)
+        .output()
+        .context("Failed to execute itack")?;
+
+    if !output.status.success() {
+        anyhow::bail!(
+            "itack {} failed: {}",
+            args.join(" "),
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+
+    Ok(())
+}
+
+/// Claim an issue for `assignee`
+pub fn claim(repo: &Path, id: &str, assignee: &str) -> Result<()> {
+    itack(repo, &["claim", id, assignee])
+}
+
+/// Mark an issue as done
+pub fn done(repo: &Path, id: &str) -> Result<()> {
+    itack(repo, &["done", id])
+}
+
+/// Append notes to the end of an issue file
+pub fn append_notes(path: &Path, notes: &str) -> Result<()> {
+    let mut f = fs::OpenOptions::new()
+        .append(true)
+        .open(path)
+        .with_context(|| format!("Failed to open {}", path.display()))?;
+    writeln!(f, "\n{}", notes.trim_end())?;
+    Ok(())
+}
+
+/// Lowercase, dash-separated form of a title for branch names
+pub fn slugify(title: &str) -> String {
+    let mut slug = String::new();
+    for c in title.chars() {
+        if c.is_ascii_alphanumeric() {
+            slug.push(c.to_ascii_lowercase());
+        } else if !slug.is_empty() && !slug.ends_with('-') {
+            slug.push('-');
+        }
+    }
+    slug.trim_end_matches('-').to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_issue_front_matter() {
+        let (title, body) = parse_issue(
+            "---\nid: 15\ntitle: \"Add user authentication\"\nstate: open\n---\n\nUsers need to log in.\n",
+        );
+        assert_eq!(title.as_deref(), Some("Add user authentication"));
+        assert_eq!(body, "Users need to log in.");
+    }
+
+    #[test]
+    fn test_parse_issue_heading() {
+        let (title, body) = parse_issue("# Fix login bug\n\nSteps to reproduce:\n1. Log in\n");
+        assert_eq!(title.as_deref(), Some("Fix login bug"));
+        assert_eq!(body, "Steps to reproduce:\n1. Log in");
+    }
+
+    #[test]
+    fn test_parse_issue_no_title() {
+        let (title, body) = parse_issue("Just some text\n");
+        assert_eq!(title, None);
+        assert_eq!(body, "Just some text");
+    }
+
+    #[test]
+    fn test_slugify() {
+        assert_eq!(
+            slugify("Add user authentication"),
+            "add-user-authentication"
+        );
+        assert_eq!(
+            slugify("Fix: crash on `ccx stop`!"),
+            "fix-crash-on-ccx-stop"
+        );
+    }
+}
@@ -0,0 +1,202 @@
+use crate::{config, tmux};
+use anyhow::{Context, Result};
+use serde::Deserialize;
+
+/// Variable naming the session in a layout's panes. It is not [`tmux::SESSION_ENV`], so
+/// sessions started by hand from a layout pane are not counted as the agent's children.
+pub const LAYOUT_SESSION_ENV: &str = "CCX_LAYOUT_SESSION";
+
+/// Where a pane opens relative to the pane it splits
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Split {
+    #[default]
+    Below,
+    Right,
+}
+
+/// One extra pane of a layout
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LayoutPane {
+    /// Shell command to run (default: a shell)
+    pub command: Option<String>,
+    /// Window to open the pane in, created on first use (default: the agent's window)
+    pub window: Option<String>,
+    pub split: Split,
+    /// Lines or columns, or a percentage like `30%`
+    pub size: Option<String>,
+}
+
+/// `[layouts.<name>]` in ~/.ccx/config.toml: panes opened next to the agent, in the
+/// session's working directory
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Layout {
+    pub panes: Vec<LayoutPane>,
+}
+
+impl Layout {
+    fn validate(&self) -> Result<()> {
+        for pane in &self.panes {
+            if let Some(size) = &pane.size {
+                let digits = size.strip_suffix('%').unwrap_or(size);
+                if digits.is_empty() || !digits.chars().all(|c| c.is_ascii_digit()) {
+                    anyhow::bail!("Invalid pane size '{}' (expected e.g. 20 or 30%)", size);
+                }
+            }
+            if let Some(window) = &pane.window
+                && (window.is_empty() || window.contains([':', '.']))
+            {
+                anyhow::bail!("Invalid window name '{}'", window);
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Look up a layout by name
+pub fn find(name: &str) -> Result<Layout> {
+    let layout = config::load_global()?
+        .layouts
+        .remove(name)
+        .with_context(|| format!("Layout '{}' not found in ~/.ccx/config.toml", name))?;
+    layout
+        .validate()
+        .with_context(|| format!("Invalid layout '{}'", name))?;
+    Ok(layout)
+}
+
+/// tmux commands opening a layout's panes. `-d` leaves the agent's pane selected, so
+/// commands aimed at the session keep reaching the agent.
+fn commands(session: &str, dir: &str, layout: &Layout) -> Vec<Vec<String>> {
+    let mut windows: Vec<&str> = Vec::new();
+    let mut commands = Vec::new();
+    for pane in &layout.panes {
+        let mut args: Vec<String> = match &pane.window {
+            Some(window) if !windows.contains(&window.as_str()) => {
+                windows.push(window);
+                vec![
+                    "new-window".into(),
+                    "-d".into(),
+                    "-t".into(),
+                    format!("{}:", session),
+                    "-n".into(),
+                    window.clone(),
+                ]
+            }
+            target => {
+                let mut args = vec![
+                    "split-window".to_string(),
+                    "-d".into(),
+                    "-t".into(),
+                    match target {
+                        Some(window) => format!("{}:{}", session, window),
+                        None => session.to_string(),
+                    },
+                ];
+                if pane.split == Split::Right {
+                    args.push("-h".into());
+                }
+                if let Some(size) = &pane.size {
+                    args.extend(["-l".into(), size.clone()]);
+                }
+                args
+            }
+        };
+        args.extend([
+            "-c".into(),
+            dir.to_string(),
+            "-e".into(),
+            format!("{}={}", LAYOUT_SESSION_ENV, session),
+        ]);
+        args.extend(pane.command.clone());
+        commands.push(args);
+    }
+    commands
+}
+
+/// Open a layout's panes in a running session
+pub fn apply(session: &str, dir: &str, layout: &Layout) -> Result<()> {
+    for args in commands(session, dir, layout) {
+        tmux::run(&args)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn layout(toml: &str) -> Layout {
+        toml::from_str(toml).unwrap()
+    }
+
+    #[test]
+    fn test_commands() {
+        let dev = layout(
+            r#"
+            panes = [
+              { command = "cargo watch -x check", split = "right", size = "40%" },
+              { window = "shell" },
+              { window = "shell", command = "tail -f log" },
+            ]
+            "#,
+        );
+        let commands = commands("ccx-1", "/src/app", &dev);
+        let env = "CCX_LAYOUT_SESSION=ccx-1";
+        assert_eq!(
+            commands[0],
+            [
+                "split-window",
+                "-d",
+                "-t",
+                "ccx-1",
+                "-h",
+                "-l",
+                "40%",
+                "-c",
+                "/src/app",
+                "-e",
+                env,
+                "cargo watch -x check"
+            ]
+        );
+        assert_eq!(
+            commands[1],
+            [
+                "new-window",
+                "-d",
+                "-t",
+                "ccx-1:",
+                "-n",
+                "shell",
+                "-c",
+                "/src/app",
+                "-e",
+                env
+            ]
+        );
+        assert_eq!(
+            commands[2][..4],
+            ["split-window", "-d", "-t", "ccx-1:shell"]
+        );
+        assert_eq!(commands[2].last().unwrap(), "tail -f log");
+    }
+
+    #[test]
+    fn test_validate() {
+        assert!(
+            layout(r#"panes = [{ size = "30%" }, { size = "12" }]"#)
+                .validate()
+                .is_ok()
+        );
+        assert!(layout(r#"panes = [{ size = "big" }]"#).validate().is_err());
+        assert!(
+            layout(r#"panes = [{ window = "a:b" }]"#)
+                .validate()
+                .is_err()
+        );
+        assert!(toml::from_str::<Layout>(r#"panes = [{ split = "left" }]"#).is_err());
+    }
+}
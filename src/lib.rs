@@ -0,0 +1,101 @@
+//! Run and manage background Claude Code sessions in tmux.
+//!
+//! [`SessionManager`] starts, lists, inspects and controls sessions; the `ccx` command
+//! line is built on it. Sessions live in tmux and their records in `~/.ccx`, so
+//! programs using this crate and the `ccx` command see the same sessions.
+//!
+//! ```no_run
+//! use ccx::{Launch, SessionManager};
+//! use std::time::Duration;
+//!
+//! let manager = SessionManager::new();
+//! let session = manager.start(Launch {
+//!     prompt: Some("fix the failing parser test".to_string()),
+//!     cwd: Some("~/src/app".to_string()),
+//!     ..Default::default()
+//! })?;
+//! let status = manager.wait(&session.name, Duration::from_secs(2))?;
+//! println!("{} {}", session.name, status);
+//! # Ok::<(), anyhow::Error>(())
+//! ```
+//!
+//! Sessions rely on tmux running the `ccx` executable in the background (to record
+//! when an agent exits, run completion actions and drive headless runs), so `ccx` must
+//! be installed on PATH, or its location set in `CCX_EXECUTABLE`.
+//!
+//! Starting a session never writes to the terminal: its progress and warnings go to
+//! the session log (`~/.ccx/logs/<session>.log`). Under the `queue` concurrency
+//! policy, starting blocks until other sessions working in the same directory finish.
+//!
+//! The items exported at the crate root, including the types their fields use, are the
+//! supported API; the hidden modules back the command line and are not. ccx is not at
+//! 1.0, so the API may change in a minor release (0.x to 0.y) but not in a patch
+//! release. Option structs implement `Default`, so build them with
+//! `..Default::default()`.
+
+#[doc(hidden)]
+pub mod adopt;
+#[doc(hidden)]
+pub mod agent;
+#[doc(hidden)]
+pub mod batch;
+#[doc(hidden)]
+pub mod config;
+#[doc(hidden)]
+pub mod environment;
+#[doc(hidden)]
+pub mod git;
+#[doc(hidden)]
+pub mod headless;
+#[doc(hidden)]
+pub mod itack;
+#[doc(hidden)]
+pub mod layout;
+mod manager;
+#[doc(hidden)]
+pub mod mcp;
+#[doc(hidden)]
+pub mod permissions;
+#[doc(hidden)]
+pub mod review;
+#[doc(hidden)]
+pub mod sandbox;
+#[doc(hidden)]
+pub mod server;
+#[doc(hidden)]
+pub mod session;
+#[doc(hidden)]
+pub mod startup;
+#[doc(hidden)]
+pub mod store;
+#[doc(hidden)]
+pub mod template;
+#[doc(hidden)]
+pub mod tmux;
+#[doc(hidden)]
+pub mod transcript;
+#[doc(hidden)]
+pub mod verify;
+
+pub use agent::AgentOptions;
+pub use config::ConcurrencyPolicy;
+pub use environment::EnvValue;
+pub use git::{GitChanges, GitSnapshot};
+pub use headless::RunResult;
+pub use manager::{Event, Events, Session, SessionManager, SessionStatus};
+pub use permissions::{PermissionMode, PermissionPolicy};
+pub use session::{Launch, StartOptions};
+pub use store::{IssueLink, Review, SessionRecord, StartAttempt, Verdict, Verification, VerifyRun};
+pub use tmux::StartOutcome;
+
+/// Shorten a path for display by replacing $HOME with ~
+#[doc(hidden)]
+pub fn shorten_path(path: &str) -> String {
+    if let Some(home) = std::env::var_os("HOME") {
+        let home_str = home.to_string_lossy();
+        if path.starts_with(home_str.as_ref()) {
+            return format!("~{}", &path[home_str.len()..]);
+        }
+    }
+    path.to_string()
+}
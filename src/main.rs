@@ -1,9 +1,12 @@
-mod tmux;
-
 use anyhow::{Context, Result};
-use clap::{CommandFactory, Parser, Subcommand};
+use ccx::{
+    SessionManager, adopt, agent, batch, config, environment, git, headless, itack, mcp,
+    permissions, server, session, shorten_path, store, template, tmux,
+};
+use clap::{Args, CommandFactory, Parser, Subcommand};
 use clap_complete::{Shell, generate};
 use std::env;
+use std::path::{Path, PathBuf};
 
 #[derive(Parser)]
 #[command(name = "ccx")]
@@ -16,13 +19,7 @@ struct Cli {
 #[derive(Subcommand)]
 enum Commands {
     /// Start a new Claude Code session with the given prompt
-    Start {
-        /// The prompt to send to Claude
-        prompt: String,
-        /// Working directory for the Claude Code session
-        #[arg(long)]
-        cwd: Option<String>,
-    },
+    Start(Box<StartArgs>),
     /// Show status of sessions (list all, or detail for a specific session)
     Status {
         /// Optional session name to show detailed output
@@ -37,11 +34,37 @@ enum Commands {
     Stop {
         /// The session name to stop
         session: String,
+        /// Also stop every session it started, and theirs
+        #[arg(long, short)]
+        recursive: bool,
     },
     /// Attach to an existing session
     Attach {
         /// The session name to attach to
         session: String,
+        /// Open the session in a tmux popup (inside tmux)
+        #[arg(long, conflicts_with = "split")]
+        popup: bool,
+        /// Open the session in a new pane split from the current one (inside tmux)
+        #[arg(long)]
+        split: bool,
+        /// Watch without being able to type into the session
+        #[arg(long, short)]
+        read_only: bool,
+    },
+    /// Register an existing tmux pane running Claude Code as a ccx session
+    Adopt {
+        /// tmux target of the pane, e.g. `work`, `work:1.0` or `%3`
+        target: String,
+        /// Human-friendly label (default: the pane's tmux session name)
+        #[arg(long)]
+        label: Option<String>,
+    },
+    /// Find tmux panes running Claude Code outside ccx and offer to adopt them
+    Discover {
+        /// Adopt every pane found without asking
+        #[arg(long, short)]
+        yes: bool,
     },
     /// Generate shell completions
     Completions {
@@ -64,6 +87,52 @@ enum Commands {
         #[arg(long, default_value = "2")]
         interval: u64,
     },
+    /// Start a session to work on an itack issue
+    Issue {
+        /// The itack issue id
+        id: String,
+        /// Repository containing the .itack directory (default: current directory)
+        #[arg(long)]
+        cwd: Option<String>,
+        /// Work in a new git worktree named after the issue
+        #[arg(long)]
+        worktree: bool,
+        /// Name to claim the issue for (default: itack_assignee config or $USER)
+        #[arg(long)]
+        assignee: Option<String>,
+        /// Run `itack done` when the session completes
+        #[arg(long)]
+        done: bool,
+        /// Append session notes to the issue file when the session completes
+        #[arg(long)]
+        notes: bool,
+        /// What to do if another session is working in the same directory
+        #[arg(long, value_enum)]
+        on_conflict: Option<config::ConcurrencyPolicy>,
+    },
+    /// Show the git changes a session produced since it started
+    Diff {
+        /// The session name
+        session: String,
+        /// Show a diffstat instead of the full patch
+        #[arg(long)]
+        stat: bool,
+    },
+    /// Launch many sessions from a manifest file
+    Batch {
+        #[command(subcommand)]
+        action: BatchAction,
+    },
+    /// Run a pipeline of dependent steps, passing outputs downstream
+    Pipeline {
+        #[command(subcommand)]
+        action: PipelineAction,
+    },
+    /// Manage prompt templates
+    Template {
+        #[command(subcommand)]
+        action: TemplateAction,
+    },
     /// View or clean up session logs
     Logs {
         #[command(subcommand)]
@@ -71,6 +140,192 @@ enum Commands {
     },
     /// Print the version
     Version,
+    /// Serve an HTTP/JSON API for listing and controlling sessions
+    Serve {
+        /// host:port to listen on, or a Unix socket path
+        #[arg(long, default_value = "127.0.0.1:7777")]
+        listen: String,
+    },
+    /// Serve ccx's tools to agents over the Model Context Protocol on stdio
+    Mcp,
+    /// Print the final answer of a headless session (or the last message of another)
+    Result {
+        /// The session name
+        session: String,
+        /// Print the full result record as JSON
+        #[arg(long)]
+        json: bool,
+    },
+    /// Wait for a session to complete and run its completion actions (internal)
+    #[command(hide = true)]
+    Supervise {
+        /// The session name
+        session: String,
+    },
+    /// Record the exit status of a session whose agent exited and remove its pane
+    /// (internal, run by tmux's pane-died hook)
+    #[command(hide = true)]
+    Reap {
+        /// The session name
+        session: String,
+    },
+    /// Run claude in print mode for a headless session and record its result (internal)
+    #[command(hide = true)]
+    RunHeadless {
+        /// The session name
+        session: String,
+        /// The claude command line
+        #[arg(last = true, required = true)]
+        command: Vec<String>,
+    },
+}
+
+#[derive(Args)]
+struct StartArgs {
+    /// The prompt to send to Claude (available as {{prompt}} in templates)
+    #[arg(required_unless_present_any = ["template", "profile"])]
+    prompt: Option<String>,
+    /// Working directory for the Claude Code session
+    #[arg(long)]
+    cwd: Option<String>,
+    /// What to do if another session is working in the same directory
+    /// (overrides the configured policy)
+    #[arg(long, value_enum)]
+    on_conflict: Option<config::ConcurrencyPolicy>,
+    /// Build the prompt from a named template
+    #[arg(long)]
+    template: Option<String>,
+    /// Template variable as KEY=VALUE (repeatable)
+    #[arg(long = "var", value_name = "KEY=VALUE", value_parser = template::parse_var)]
+    vars: Vec<(String, String)>,
+    /// Apply a named profile from ~/.ccx/config.toml
+    #[arg(long)]
+    profile: Option<String>,
+    /// Tag the session (repeatable)
+    #[arg(long = "tag")]
+    tags: Vec<String>,
+    /// When the session completes, start a reviewer session with this prompt plus the
+    /// session's diff and final message, and record its verdict
+    #[arg(long, value_name = "PROMPT")]
+    review: Option<String>,
+    /// Shell command run in the session's directory when it completes, marking it
+    /// verified or failed (overrides the configured `verify`)
+    #[arg(long, value_name = "CMD", conflicts_with = "no_verify")]
+    verify: Option<String>,
+    /// Send a failed verification back into the session up to N times
+    #[arg(long, value_name = "N")]
+    verify_rounds: Option<u32>,
+    /// Skip the configured verification command
+    #[arg(long)]
+    no_verify: bool,
+    /// Seconds to wait for Claude Code to start before relaunching it
+    #[arg(long, value_name = "SECS")]
+    start_timeout: Option<u64>,
+    /// Times to relaunch a session that fails to start
+    #[arg(long, value_name = "N")]
+    retries: Option<u32>,
+    /// How tool permission prompts are handled: skip them with
+    /// --dangerously-skip-permissions, or answer them from the configured allow and
+    /// deny lists (overrides the configured mode)
+    #[arg(long, value_enum)]
+    permissions: Option<permissions::PermissionMode>,
+    /// Run the agent under a sandbox profile from ~/.ccx/config.toml
+    /// (`default`: bubblewrap with only the working directory writable)
+    #[arg(long, value_name = "PROFILE")]
+    sandbox: Option<String>,
+    /// Environment variable for the agent as KEY=VALUE (repeatable)
+    #[arg(long = "env", value_name = "KEY=VALUE", value_parser = environment::parse_env)]
+    env: Vec<(String, String)>,
+    /// Read environment variables from a dotenv-style file (repeatable)
+    #[arg(long = "env-file", value_name = "PATH")]
+    env_files: Vec<String>,
+    /// Model for the agent: an alias (opus, sonnet, haiku) or a full model name
+    #[arg(long)]
+    model: Option<String>,
+    /// Stop the agent after N turns (headless sessions only)
+    #[arg(long, value_name = "N", value_parser = clap::value_parser!(u32).range(1..), requires = "headless")]
+    max_turns: Option<u32>,
+    /// Text appended to Claude Code's system prompt
+    #[arg(
+        long,
+        value_name = "TEXT",
+        conflicts_with = "append_system_prompt_file"
+    )]
+    append_system_prompt: Option<String>,
+    /// Append the contents of a file to Claude Code's system prompt
+    #[arg(long, value_name = "PATH")]
+    append_system_prompt_file: Option<String>,
+    /// Tools allowed without a prompt, e.g. Read or "Bash(cargo test:*)" (repeatable)
+    #[arg(long, value_name = "RULE")]
+    allowed_tools: Vec<String>,
+    /// MCP server config file (JSON with an mcpServers object)
+    #[arg(long, value_name = "PATH")]
+    mcp_config: Option<String>,
+    /// Run claude non-interactively in print mode, recording its streamed events,
+    /// exit status and final result (see `ccx result`)
+    #[arg(long)]
+    headless: bool,
+    /// Most child sessions this session's agent may start with ccx
+    #[arg(long, value_name = "N")]
+    max_children: Option<u32>,
+    /// Open the panes of a layout from ~/.ccx/config.toml next to the agent
+    #[arg(long)]
+    layout: Option<String>,
+}
+
+#[derive(Subcommand)]
+enum BatchAction {
+    /// Launch the tasks in a manifest (.toml with [[task]] tables, or .jsonl)
+    Run {
+        /// Path to the manifest file
+        manifest: String,
+        /// Maximum number of sessions running at once (overrides the manifest)
+        #[arg(long)]
+        max_concurrent: Option<usize>,
+    },
+    /// Show the state of each task in a batch
+    Status {
+        /// The batch id
+        id: String,
+    },
+    /// List batches
+    List,
+}
+
+#[derive(Subcommand)]
+enum PipelineAction {
+    /// Run a pipeline definition ([[step]] tables) until every step finishes
+    Run {
+        /// Path to the pipeline file
+        file: String,
+        /// Maximum number of sessions running at once
+        #[arg(long)]
+        max_concurrent: Option<usize>,
+    },
+    /// Show the state of each step in a pipeline
+    Status {
+        /// The pipeline id
+        id: String,
+    },
+}
+
+#[derive(Subcommand)]
+enum TemplateAction {
+    /// List available templates
+    List,
+    /// Print a template
+    Show {
+        /// The template name
+        name: String,
+    },
+    /// Open a template in $EDITOR, creating it if needed
+    Edit {
+        /// The template name
+        name: String,
+        /// Edit the current repo's template instead of the global one
+        #[arg(long)]
+        repo: bool,
+    },
 }
 
 #[derive(Subcommand)]
@@ -92,37 +347,131 @@ enum LogsAction {
 
 fn main() -> Result<()> {
     let cli = Cli::parse();
+    session::on_notice(|message| eprintln!("{}", message));
 
     match cli.command {
-        Commands::Start { prompt, cwd } => cmd_start(&prompt, cwd.as_deref()),
+        Commands::Start(args) => cmd_start(*args),
         Commands::Status { session, lines } => cmd_status(session.as_deref(), lines),
         Commands::List => cmd_list(),
-        Commands::Stop { session } => cmd_stop(&session),
-        Commands::Attach { session } => cmd_attach(&session),
+        Commands::Stop { session, recursive } => cmd_stop(&session, recursive),
+        Commands::Attach {
+            session,
+            popup,
+            split,
+            read_only,
+        } => {
+            let mode = match (popup, split) {
+                (true, _) => tmux::AttachMode::Popup,
+                (_, true) => tmux::AttachMode::Split,
+                _ => tmux::AttachMode::Terminal,
+            };
+            cmd_attach(&session, mode, read_only)
+        }
+        Commands::Adopt { target, label } => cmd_adopt(&target, label),
+        Commands::Discover { yes } => cmd_discover(yes),
         Commands::Completions { shell } => cmd_completions(shell),
         Commands::Send { session, message } => cmd_send(&session, &message),
         Commands::Watch { session, interval } => cmd_watch(&session, interval),
+        Commands::Issue {
+            id,
+            cwd,
+            worktree,
+            assignee,
+            done,
+            notes,
+            on_conflict,
+        } => cmd_issue(
+            &id,
+            cwd.as_deref(),
+            worktree,
+            assignee.as_deref(),
+            done,
+            notes,
+            on_conflict,
+        ),
+        Commands::Diff { session, stat } => cmd_diff(&session, stat),
+        Commands::Batch { action } => cmd_batch(action),
+        Commands::Pipeline { action } => cmd_pipeline(action),
+        Commands::Template { action } => cmd_template(action),
         Commands::Logs { action } => cmd_logs(action),
         Commands::Version => cmd_version(),
+        Commands::Serve { listen } => server::serve(&listen),
+        Commands::Mcp => mcp::serve(),
+        Commands::Result { session, json } => cmd_result(&session, json),
+        Commands::Supervise { session } => session::supervise(&session),
+        Commands::Reap { session } => {
+            session::reap(&session);
+            Ok(())
+        }
+        Commands::RunHeadless { session, command } => {
+            let code = headless::run(&session, &command)?;
+            std::process::exit(code)
+        }
     }
 }
 
-/// Shorten a path for display by replacing $HOME with ~
-fn shorten_path(path: &str) -> String {
-    if let Some(home) = env::var_os("HOME") {
-        let home_str = home.to_string_lossy();
-        if path.starts_with(home_str.as_ref()) {
-            return format!("~{}", &path[home_str.len()..]);
-        }
+/// Show the Claude Code options a session was started with
+fn print_agent_options(agent: &agent::AgentOptions) {
+    if let Some(model) = &agent.model {
+        println!("model: {}", model);
+    }
+    if let Some(max_turns) = agent.max_turns {
+        println!("max turns: {}", max_turns);
+    }
+    if let Some(prompt) = &agent.append_system_prompt {
+        let first = prompt.lines().next().unwrap_or_default();
+        let more = if prompt.lines().nth(1).is_some() {
+            " ..."
+        } else {
+            ""
+        };
+        println!("system prompt append: {}{}", first, more);
+    }
+    if !agent.allowed_tools.is_empty() {
+        println!("allowed tools: {}", agent.allowed_tools.join(", "));
+    }
+    if let Some(path) = &agent.mcp_config {
+        println!("mcp config: {}", shorten_path(path));
     }
-    path.to_string()
 }
 
-fn cmd_start(prompt: &str, cwd: Option<&str>) -> Result<()> {
-    let session_name = tmux::generate_session_name();
-    tmux::create_session(&session_name, prompt, cwd)?;
-    println!("Started session: {}", session_name);
-    println!("Attach with: ccx attach {}", session_name);
+fn cmd_start(args: StartArgs) -> Result<()> {
+    let launch = session::Launch {
+        prompt: args.prompt,
+        template: args.template,
+        vars: args.vars.into_iter().collect(),
+        cwd: args.cwd,
+        on_conflict: args.on_conflict,
+        profile: args.profile,
+        tags: args.tags,
+        review: args.review,
+        verify: args.verify,
+        verify_rounds: args.verify_rounds,
+        sandbox: args.sandbox,
+        env: args
+            .env
+            .into_iter()
+            .map(|(key, value)| (key, environment::EnvValue::Plain(value)))
+            .collect(),
+        env_files: args.env_files,
+        model: args.model,
+        max_turns: args.max_turns,
+        append_system_prompt: args.append_system_prompt,
+        append_system_prompt_file: args.append_system_prompt_file,
+        allowed_tools: args.allowed_tools,
+        mcp_config: args.mcp_config,
+        max_children: args.max_children,
+        layout: args.layout,
+    };
+    let mut opts = launch.prepare()?;
+    opts.no_verify = args.no_verify;
+    opts.start_timeout = args.start_timeout;
+    opts.start_retries = args.retries;
+    opts.permissions = args.permissions;
+    opts.headless = args.headless;
+    let session = SessionManager::new().start_with(opts)?;
+    println!("Started session: {}", session.name);
+    println!("Attach with: ccx attach {}", session.name);
     Ok(())
 }
 
@@ -130,40 +479,63 @@ fn cmd_status(session: Option<&str>, num_lines: i32) -> Result<()> {
     match session {
         Some(name) => {
             // Show detailed output for a specific session
-            if !tmux::session_exists(name) {
-                anyhow::bail!("Session '{}' does not exist", name);
+            let manager = SessionManager::new();
+            let session = manager.get(name)?;
+            if !session.live {
+                println!("{} {}", name, session.status);
+                return Ok(());
             }
-            match tmux::capture_pane(name, num_lines) {
-                Ok(content) => {
-                    // Take last N non-empty lines
-                    let lines: Vec<&str> =
-                        content.lines().filter(|l| !l.trim().is_empty()).collect();
-                    let last_n: Vec<&str> = lines
-                        .iter()
-                        .rev()
-                        .take(num_lines as usize)
-                        .rev()
-                        .cloned()
-                        .collect();
-                    for line in last_n {
-                        println!("{}", line);
-                    }
-                }
+            let mut header = false;
+            if let Some(parent) = &session.parent {
+                println!("parent: {}", parent);
+                header = true;
+            }
+            let children = session::children(name)?;
+            if !children.is_empty() {
+                let names: Vec<&str> = children.iter().map(|c| c.name.as_str()).collect();
+                println!("children: {}", names.join(", "));
+                header = true;
+            }
+            if let Some(record) = store::load(name)?
+                && !record.agent.is_empty()
+            {
+                print_agent_options(&record.agent);
+                header = true;
+            }
+            if header {
+                println!();
+            }
+            match manager.output(name, num_lines as usize) {
+                Ok(output) => println!("{}", output),
                 Err(e) => println!("Could not capture output: {}", e),
             }
         }
         None => {
-            // List all sessions with just name and title
-            let sessions = tmux::list_sessions()?;
+            // List all sessions with just name and title, then the ones whose agent
+            // has exited
+            let (sessions, ended): (Vec<ccx::Session>, Vec<ccx::Session>) = SessionManager::new()
+                .list()?
+                .into_iter()
+                .partition(|s| s.live);
 
-            if sessions.is_empty() {
+            if sessions.is_empty() && ended.is_empty() {
                 println!("No active ccx sessions");
                 return Ok(());
             }
 
-            for session in sessions {
+            // Group sessions by the directory they occupy to flag shared ones
+            let dir_keys: Vec<Option<String>> = sessions
+                .iter()
+                .map(|s| session::session_dir_key(&s.name))
+                .collect();
+
+            for (i, depth) in tree_order(&sessions) {
+                let session = &sessions[i];
                 let title = tmux::get_pane_title(&session.name).unwrap_or_default();
                 let status = tmux::parse_status_from_title(&title);
+                if status == tmux::SessionStatus::Done {
+                    session::record_completion(&session.name);
+                }
                 let status_display = match status {
                     tmux::SessionStatus::Unknown => String::new(),
                     _ => format!(" *{}*", status),
@@ -173,12 +545,95 @@ fn cmd_status(session: Option<&str>, num_lines: i32) -> Result<()> {
                 } else {
                     format!(" [{}]", title)
                 };
+                let record = store::load(&session.name).ok().flatten();
+                let label_display = record
+                    .as_ref()
+                    .and_then(|r| r.label.as_ref())
+                    .map(|label| format!(" ({})", label))
+                    .unwrap_or_default();
+                let issue_display = record
+                    .as_ref()
+                    .and_then(|r| r.issue.as_ref())
+                    .map(|issue| format!(" (issue {})", issue.id))
+                    .unwrap_or_default();
+                let review_display = record
+                    .as_ref()
+                    .map(|r| match (&r.review, &r.reviews) {
+                        (Some(review), _) => match (review.verdict, &review.session) {
+                            (Some(verdict), _) => format!(" (review: {})", verdict),
+                            (None, Some(reviewer)) => format!(" (review: by {})", reviewer),
+                            (None, None) => " (review: pending)".to_string(),
+                        },
+                        (None, Some(worker)) => format!(" (reviewing {})", worker),
+                        (None, None) => String::new(),
+                    })
+                    .unwrap_or_default();
+                let verify_display = record
+                    .as_ref()
+                    .and_then(|r| r.verify.as_ref())
+                    .map(|v| match v.passed() {
+                        Some(true) => " (verified)",
+                        Some(false) => " (verify failed)",
+                        None => "",
+                    })
+                    .unwrap_or_default();
+                let sandbox_display = record
+                    .as_ref()
+                    .and_then(|r| r.sandbox.as_ref())
+                    .map(|name| format!(" (sandbox {})", name))
+                    .unwrap_or_default();
+                let tags_display = record
+                    .as_ref()
+                    .filter(|r| !r.tags.is_empty())
+                    .map(|r| format!(" #{}", r.tags.join(" #")))
+                    .unwrap_or_default();
                 let cwd_display = tmux::get_pane_cwd(&session.name)
                     .map(|p| format!(" {}", shorten_path(&p)))
                     .unwrap_or_default();
+                let sharing: Vec<&str> = sessions
+                    .iter()
+                    .zip(&dir_keys)
+                    .filter(|(other, key)| {
+                        other.name != session.name && key.is_some() && **key == dir_keys[i]
+                    })
+                    .map(|(other, _)| other.name.as_str())
+                    .collect();
+                let shared_display = if sharing.is_empty() {
+                    String::new()
+                } else {
+                    format!(" !shared-dir with {}", sharing.join(", "))
+                };
+                println!(
+                    "{}{}{}{}{}{}{}{}{}{}{}{}",
+                    tree_prefix(depth),
+                    session.name,
+                    label_display,
+                    status_display,
+                    verify_display,
+                    sandbox_display,
+                    issue_display,
+                    review_display,
+                    tags_display,
+                    title_display,
+                    cwd_display,
+                    shared_display
+                );
+            }
+
+            for (i, depth) in tree_order(&ended) {
+                let session = &ended[i];
+                let label_display = session
+                    .label
+                    .as_ref()
+                    .map(|label| format!(" ({})", label))
+                    .unwrap_or_default();
                 println!(
-                    "{}{}{}{}",
-                    session.name, status_display, title_display, cwd_display
+                    "{}{}{} *{}* {}",
+                    tree_prefix(depth),
+                    session.name,
+                    label_display,
+                    session.status,
+                    shorten_path(session.cwd.as_deref().unwrap_or_default())
                 );
             }
         }
@@ -187,8 +642,28 @@ fn cmd_status(session: Option<&str>, num_lines: i32) -> Result<()> {
     Ok(())
 }
 
+/// Indices and depths of `sessions` with each session's children after it
+fn tree_order(sessions: &[ccx::Session]) -> Vec<(usize, usize)> {
+    let nodes: Vec<(&str, Option<&str>)> = sessions
+        .iter()
+        .map(|s| (s.name.as_str(), s.parent.as_deref()))
+        .collect();
+    session::tree_order(&nodes)
+}
+
+fn tree_prefix(depth: usize) -> String {
+    match depth {
+        0 => String::new(),
+        _ => format!("{}└─ ", "   ".repeat(depth - 1)),
+    }
+}
+
 fn cmd_list() -> Result<()> {
-    let sessions = tmux::list_sessions()?;
+    let sessions: Vec<ccx::Session> = SessionManager::new()
+        .list()?
+        .into_iter()
+        .filter(|s| s.live)
+        .collect();
 
     if sessions.is_empty() {
         println!("No active ccx sessions");
@@ -208,22 +683,75 @@ fn cmd_list() -> Result<()> {
     Ok(())
 }
 
-fn cmd_stop(session: &str) -> Result<()> {
-    if !tmux::session_exists(session) {
-        anyhow::bail!("Session '{}' does not exist", session);
+fn cmd_stop(session: &str, recursive: bool) -> Result<()> {
+    let manager = SessionManager::new();
+    if !recursive {
+        manager.stop(session)?;
+        println!("Stopped session: {}", session);
+        return Ok(());
+    }
+    for stopped in manager.stop_recursive(session)? {
+        println!("Stopped session: {}", stopped);
     }
-
-    tmux::kill_session(session)?;
-    println!("Stopped session: {}", session);
     Ok(())
 }
 
-fn cmd_attach(session: &str) -> Result<()> {
+fn cmd_attach(session: &str, mode: tmux::AttachMode, read_only: bool) -> Result<()> {
     if !tmux::session_exists(session) {
         anyhow::bail!("Session '{}' does not exist", session);
     }
 
-    tmux::attach_session(session)
+    tmux::attach_session(session, mode, read_only)
+}
+
+fn cmd_adopt(target: &str, label: Option<String>) -> Result<()> {
+    let session = adopt::adopt(target, label)?;
+    println!("Adopted {} as session: {}", target, session);
+    println!("Attach with: ccx attach {}", session);
+    Ok(())
+}
+
+fn cmd_discover(yes: bool) -> Result<()> {
+    use std::io::{BufRead, IsTerminal, Write};
+
+    let panes = adopt::discover()?;
+    if panes.is_empty() {
+        println!("No Claude Code panes found outside ccx");
+        return Ok(());
+    }
+    let interactive = !yes && std::io::stdin().is_terminal();
+    for pane in &panes {
+        println!(
+            "{:<16} {:<6} {}  {}",
+            pane.target,
+            pane.id,
+            shorten_path(&pane.cwd),
+            pane.title
+        );
+        if pane.other_server {
+            println!("  on your default tmux server; ccx can only adopt panes on its own");
+            continue;
+        }
+        if interactive {
+            print!("Adopt {}? [y/N] ", pane.target);
+            std::io::stdout().flush()?;
+            let mut answer = String::new();
+            std::io::stdin().lock().read_line(&mut answer)?;
+            if !answer.trim().eq_ignore_ascii_case("y") {
+                continue;
+            }
+        }
+        if yes || interactive {
+            // Targets go stale as panes move, so adopt by pane id
+            let session = adopt::adopt(&pane.id, None)?;
+            println!("Adopted {} as session: {}", pane.target, session);
+        }
+    }
+    if !yes && !interactive {
+        println!();
+        println!("Adopt with: ccx adopt <target>, or ccx discover --yes for all");
+    }
+    Ok(())
 }
 
 fn cmd_completions(shell: Shell) -> Result<()> {
@@ -233,15 +761,45 @@ fn cmd_completions(shell: Shell) -> Result<()> {
 }
 
 fn cmd_send(session: &str, message: &str) -> Result<()> {
-    if !tmux::session_exists(session) {
-        anyhow::bail!("Session '{}' does not exist", session);
-    }
-
-    tmux::send_keys(session, message)?;
+    SessionManager::new().send(session, message)?;
     println!("Sent message to session: {}", session);
     Ok(())
 }
 
+fn cmd_result(session: &str, json: bool) -> Result<()> {
+    let record = SessionManager::new().record(session)?;
+    if !record.headless {
+        // Interactive sessions have no exit status; show their last message instead
+        let message = record
+            .final_message
+            .with_context(|| format!("Session '{}' has no final message yet", session))?;
+        println!("{}", message);
+        return Ok(());
+    }
+    let Some(result) = record.result else {
+        anyhow::bail!("Session '{}' is still running", session);
+    };
+    if json {
+        println!("{}", serde_json::to_string_pretty(&result)?);
+    } else if let Some(text) = &result.result {
+        println!("{}", text);
+    }
+    if !result.succeeded() {
+        eprintln!(
+            "Session '{}' failed with exit status {} ({})",
+            session,
+            result.exit_code,
+            result.subtype.as_deref().unwrap_or("no result")
+        );
+        // Exit with the run's status so scripts can rely on it
+        std::process::exit(match result.exit_code {
+            0 => 1,
+            code => code,
+        });
+    }
+    Ok(())
+}
+
 fn cmd_watch(session: &str, interval: u64) -> Result<()> {
     use std::io::{self, Write};
     use std::thread;
@@ -284,6 +842,7 @@ fn cmd_watch(session: &str, interval: u64) -> Result<()> {
 
         // Check if done
         if status == tmux::SessionStatus::Done {
+            session::record_completion(session);
             println!("\nSession completed.");
             break;
         }
@@ -294,6 +853,261 @@ fn cmd_watch(session: &str, interval: u64) -> Result<()> {
     Ok(())
 }
 
+fn cmd_issue(
+    id: &str,
+    cwd: Option<&str>,
+    worktree: bool,
+    assignee: Option<&str>,
+    done: bool,
+    notes: bool,
+    on_conflict: Option<config::ConcurrencyPolicy>,
+) -> Result<()> {
+    let dir = session::resolve_cwd(cwd)?;
+    let repo = git::toplevel(&dir).map(PathBuf::from).unwrap_or(dir);
+    let settings = config::load_for(&repo)?;
+    let issue = itack::find_issue(&repo, id)?;
+
+    let assignee = assignee
+        .map(str::to_string)
+        .or(settings.itack_assignee.clone())
+        .or_else(|| env::var("USER").ok())
+        .unwrap_or_else(|| "ccx".to_string());
+    let template = settings
+        .issue_template
+        .as_deref()
+        .unwrap_or(itack::DEFAULT_PROMPT);
+    let prompt = template::render(template, &issue.vars());
+
+    let repo_str = repo.to_string_lossy().to_string();
+    let worktree_branch = worktree.then(|| {
+        format!(
+            "{}-issue-{}-{}",
+            session::repo_name(&repo_str),
+            id,
+            itack::slugify(&issue.title)
+        )
+    });
+
+    let session_name = session::start(session::StartOptions {
+        prompt,
+        cwd: Some(repo_str.clone()),
+        on_conflict,
+        worktree_branch,
+        issue: Some(store::IssueLink {
+            id: id.to_string(),
+            repo: repo_str,
+            file: issue.path.to_string_lossy().to_string(),
+            mark_done: done,
+            add_notes: notes,
+        }),
+        ..Default::default()
+    })?;
+    // Claimed only once the session runs, so a failed start leaves the issue free;
+    // a session whose claim fails is stopped rather than left working unclaimed
+    if let Err(e) = itack::claim(&repo, id, &assignee) {
+        let _ = SessionManager::new().stop(&session_name);
+        return Err(e.context(format!(
+            "Failed to claim issue {}; stopped session {}",
+            id, session_name
+        )));
+    }
+    println!("Claimed issue {} for {}: {}", id, assignee, issue.title);
+    println!("Started session: {}", session_name);
+    println!("Attach with: ccx attach {}", session_name);
+    Ok(())
+}
+
+fn cmd_diff(session: &str, stat: bool) -> Result<()> {
+    let Some(record) = store::load(session)? else {
+        anyhow::bail!("No record found for session '{}'", session);
+    };
+    let Some(start) = &record.git_start else {
+        anyhow::bail!(
+            "Session '{}' was not started in a git repository ({})",
+            session,
+            shorten_path(&record.cwd)
+        );
+    };
+
+    println!(
+        "Started on {} at {}",
+        start.branch.as_deref().unwrap_or("(detached)"),
+        start.head.as_deref().unwrap_or("(no commits)")
+    );
+    if start.dirty {
+        println!("Note: working tree was dirty at start; diff includes those changes");
+    }
+
+    let changes = git::changes_since(start)?;
+    if !changes.commits.is_empty() {
+        println!("\nCommits:");
+        for commit in &changes.commits {
+            println!("  {}", commit);
+        }
+    }
+
+    let diff = git::diff_since(start, stat)?;
+    if diff.is_empty() {
+        println!("\nNo changes");
+    } else {
+        println!("\n{}", diff);
+    }
+
+    Ok(())
+}
+
+fn cmd_batch(action: BatchAction) -> Result<()> {
+    match action {
+        BatchAction::Run {
+            manifest,
+            max_concurrent,
+        } => {
+            let batch = batch::run(
+                Path::new(&manifest),
+                batch::Kind::Batch,
+                max_concurrent,
+                &mut |line| println!("{}", line),
+            )?;
+            println!();
+            print_batch(&batch);
+        }
+        BatchAction::Status { id } => {
+            let batch = batch::BatchRecord::load(&id)?;
+            print_batch(&batch);
+        }
+        BatchAction::List => {
+            let batches = batch::BatchRecord::list()?;
+            if batches.is_empty() {
+                println!("No batches found");
+                return Ok(());
+            }
+            for b in batches {
+                println!(
+                    "{} ({} task(s)) {}",
+                    b.id,
+                    b.tasks.len(),
+                    shorten_path(&b.manifest)
+                );
+            }
+        }
+    }
+    Ok(())
+}
+
+fn cmd_pipeline(action: PipelineAction) -> Result<()> {
+    match action {
+        PipelineAction::Run {
+            file,
+            max_concurrent,
+        } => {
+            let pipeline = batch::run(
+                Path::new(&file),
+                batch::Kind::Pipeline,
+                max_concurrent,
+                &mut |line| println!("{}", line),
+            )?;
+            println!();
+            print_batch(&pipeline);
+            if pipeline.has_failures() {
+                anyhow::bail!("Pipeline {} failed", pipeline.id);
+            }
+        }
+        PipelineAction::Status { id } => {
+            let pipeline = batch::BatchRecord::load(&id)?;
+            print_batch(&pipeline);
+        }
+    }
+    Ok(())
+}
+
+/// Print a table of a batch's tasks and a summary of their states
+fn print_batch(batch: &batch::BatchRecord) {
+    println!("{} ({})", batch.id, shorten_path(&batch.manifest));
+    println!("{:<20} {:<14} {:<10} CWD", "TASK", "SESSION", "STATUS");
+    println!("{:-<20} {:-<14} {:-<10} {:-<20}", "", "", "", "");
+
+    let mut counts: Vec<(String, usize)> = Vec::new();
+    for task in &batch.tasks {
+        let status = batch::task_status(task);
+        let session = match &task.state {
+            batch::TaskState::Started { session } | batch::TaskState::Stopped { session, .. } => {
+                session.as_str()
+            }
+            _ => "-",
+        };
+        let cwd = task.cwd.as_deref().map(shorten_path).unwrap_or_default();
+        println!("{:<20} {:<14} {:<10} {}", task.name, session, status, cwd);
+        match &task.state {
+            batch::TaskState::Skipped { reason } | batch::TaskState::Stopped { reason, .. } => {
+                println!("  {}", reason)
+            }
+            batch::TaskState::Error { message } => println!("  {}", message),
+            _ => {}
+        }
+
+        match counts.iter_mut().find(|(s, _)| *s == status) {
+            Some((_, n)) => *n += 1,
+            None => counts.push((status, 1)),
+        }
+    }
+
+    let summary: Vec<String> = counts.iter().map(|(s, n)| format!("{} {}", n, s)).collect();
+    println!("\n{}", summary.join(", "));
+}
+
+fn cmd_template(action: TemplateAction) -> Result<()> {
+    let cwd = env::current_dir().context("Failed to get current directory")?;
+    let repo = git::toplevel(&cwd).map(PathBuf::from);
+
+    match action {
+        TemplateAction::List => {
+            let templates = template::list(repo.as_deref())?;
+            if templates.is_empty() {
+                println!("No templates found");
+                return Ok(());
+            }
+            for t in templates {
+                println!("{} ({})", t.name, t.scope);
+            }
+        }
+        TemplateAction::Show { name } => {
+            let t = template::find(&name, repo.as_deref())?;
+            let content = std::fs::read_to_string(&t.path)?;
+            println!(
+                "# {} ({})",
+                shorten_path(&t.path.to_string_lossy()),
+                t.scope
+            );
+            print!("{}", content);
+        }
+        TemplateAction::Edit {
+            name,
+            repo: in_repo,
+        } => {
+            let scope = if in_repo {
+                template::Scope::Repo
+            } else {
+                template::Scope::Global
+            };
+            let path = template::template_path(scope, repo.as_deref(), &name)?;
+            if let Some(dir) = path.parent() {
+                std::fs::create_dir_all(dir)
+                    .with_context(|| format!("Failed to create {}", dir.display()))?;
+            }
+            let editor = env::var("EDITOR").unwrap_or_else(|_| "vi".to_string());
+            let status = std::process::Command::new(&editor)
+                .arg(&path)
+                .status()
+                .with_context(|| format!("Failed to run editor '{}'", editor))?;
+            if !status.success() {
+                anyhow::bail!("Editor exited with {}", status);
+            }
+        }
+    }
+
+    Ok(())
+}
+
 fn logs_dir() -> Result<std::path::PathBuf> {
     let home = env::var("HOME").context("HOME not set")?;
     Ok(std::path::Path::new(&home).join(".ccx").join("logs"))
@@ -0,0 +1,450 @@
+use crate::session::{self, Launch, StartOptions};
+use crate::store::{self, SessionRecord};
+use crate::tmux;
+use anyhow::{Context, Result};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::thread;
+use std::time::Duration;
+
+/// Where a session is, combining its pane title while it runs with its record after
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(tag = "state", rename_all = "snake_case")]
+#[non_exhaustive]
+pub enum SessionStatus {
+    /// Launched, but Claude Code has not reported working yet
+    Starting,
+    Working,
+    /// Blocked on a prompt ccx escalated to a person
+    WaitingForInput,
+    /// Completed its work
+    Done,
+    /// Failed to start, or was stopped before completing
+    Failed,
+    /// The agent process exited without completing cleanly
+    Exited {
+        code: i32,
+    },
+}
+
+impl SessionStatus {
+    /// Whether the session will not change status on its own any more
+    pub fn is_finished(&self) -> bool {
+        matches!(
+            self,
+            SessionStatus::Done | SessionStatus::Failed | SessionStatus::Exited { .. }
+        )
+    }
+
+    fn from_title(title: &str) -> SessionStatus {
+        match tmux::parse_status_from_title(title) {
+            tmux::SessionStatus::InProgress => SessionStatus::Working,
+            tmux::SessionStatus::Done => SessionStatus::Done,
+            tmux::SessionStatus::Failed => SessionStatus::Failed,
+            tmux::SessionStatus::WaitingForInput => SessionStatus::WaitingForInput,
+            tmux::SessionStatus::Unknown => SessionStatus::Starting,
+        }
+    }
+
+    /// Status of a session that is no longer running, from its record
+    pub fn from_record(record: &SessionRecord) -> SessionStatus {
+        match (record.exit_code, record.completed_at) {
+            (Some(0) | None, Some(_)) => SessionStatus::Done,
+            (Some(code), _) => SessionStatus::Exited { code },
+            (None, None) => SessionStatus::Failed,
+        }
+    }
+}
+
+impl std::fmt::Display for SessionStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SessionStatus::Starting => write!(f, "starting"),
+            SessionStatus::Working => write!(f, "working"),
+            SessionStatus::WaitingForInput => write!(f, "waiting-for-input"),
+            SessionStatus::Done => write!(f, "done"),
+            SessionStatus::Failed => write!(f, "failed"),
+            SessionStatus::Exited { code } => write!(f, "exited ({})", code),
+        }
+    }
+}
+
+/// A snapshot of one session
+#[derive(Debug, Clone, Serialize)]
+#[non_exhaustive]
+pub struct Session {
+    pub name: String,
+    pub status: SessionStatus,
+    /// Whether its tmux session is still running
+    pub live: bool,
+    /// Whether a terminal is attached to it
+    pub attached: bool,
+    /// Pane title while the session is live, e.g. `⠐ Fixing the parser`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+    /// Working directory the session was started in
+    pub cwd: Option<String>,
+    pub label: Option<String>,
+    pub tags: Vec<String>,
+    /// Session whose agent started this one
+    pub parent: Option<String>,
+    /// Unix timestamp of session creation
+    pub created_at: Option<u64>,
+}
+
+impl Session {
+    fn new(name: &str, live: bool, attached: bool, record: Option<&SessionRecord>) -> Session {
+        let title = live.then(|| tmux::get_pane_title(name).unwrap_or_default());
+        let status = match (&title, record) {
+            (Some(title), _) => SessionStatus::from_title(title),
+            (None, Some(record)) => SessionStatus::from_record(record),
+            (None, None) => SessionStatus::Failed,
+        };
+        Session {
+            name: name.to_string(),
+            status,
+            live,
+            attached,
+            title: title.filter(|t| !t.is_empty()),
+            cwd: record.map(|r| r.cwd.clone()),
+            label: record.and_then(|r| r.label.clone()),
+            tags: record.map(|r| r.tags.clone()).unwrap_or_default(),
+            parent: record.and_then(|r| r.parent.clone()),
+            created_at: record.map(|r| r.created_at),
+        }
+    }
+}
+
+/// A change seen by [`SessionManager::watch`]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+#[non_exhaustive]
+pub enum Event {
+    /// A session appeared
+    Started {
+        session: String,
+        status: SessionStatus,
+    },
+    /// A session's status changed
+    StatusChanged {
+        session: String,
+        from: SessionStatus,
+        to: SessionStatus,
+    },
+    /// A session was removed, e.g. stopped before it ended
+    Removed { session: String },
+}
+
+/// Starts, inspects and controls ccx sessions. Sessions live in tmux and their records
+/// in ~/.ccx, so any number of managers (and the `ccx` command) see the same sessions.
+#[derive(Debug, Clone, Default)]
+#[non_exhaustive]
+pub struct SessionManager {}
+
+impl SessionManager {
+    pub fn new() -> SessionManager {
+        SessionManager {}
+    }
+
+    /// Start a session from a prompt or template, applying its profile. Blocks while
+    /// the session is queued behind others in its directory (the `queue` policy).
+    pub fn start(&self, launch: Launch) -> Result<Session> {
+        self.start_with(launch.prepare()?)
+    }
+
+    /// Start a session from fully resolved options
+    pub fn start_with(&self, opts: StartOptions) -> Result<Session> {
+        let name = session::start(opts)?;
+        self.get(&name)
+    }
+
+    /// Live sessions, then sessions that ended within the last day. Listing reaps
+    /// sessions whose agent has exited: their exit is recorded and their agent pane
+    /// closed, as the pane-died hook would.
+    pub fn list(&self) -> Result<Vec<Session>> {
+        session::close_adopted()?;
+        let mut sessions = Vec::new();
+        for live in tmux::list_sessions()? {
+            // A session whose agent has exited is reaped and listed as ended below
+            if !session::is_live(&live.name) {
+                continue;
+            }
+            let record = store::load(&live.name).ok().flatten();
+            sessions.push(Session::new(
+                &live.name,
+                true,
+                live.attached,
+                record.as_ref(),
+            ));
+        }
+        for record in session::recently_ended()? {
+            sessions.push(Session::new(&record.name, false, false, Some(&record)));
+        }
+        Ok(sessions)
+    }
+
+    /// Look up one session, live or ended
+    pub fn get(&self, name: &str) -> Result<Session> {
+        if session::is_live(name) {
+            let attached = tmux::list_sessions()?
+                .iter()
+                .any(|s| s.name == name && s.attached);
+            let record = store::load(name)?;
+            return Ok(Session::new(name, true, attached, record.as_ref()));
+        }
+        let record =
+            store::load(name)?.with_context(|| format!("Session '{}' does not exist", name))?;
+        Ok(Session::new(name, false, false, Some(&record)))
+    }
+
+    /// Everything ccx recorded about a session
+    pub fn record(&self, name: &str) -> Result<SessionRecord> {
+        store::load(name)?.with_context(|| format!("No record for session '{}'", name))
+    }
+
+    /// Type a message into a live session and submit it
+    pub fn send(&self, name: &str, message: &str) -> Result<()> {
+        self.require_live(name)?;
+        if store::load(name)?.is_some_and(|r| r.headless) {
+            anyhow::bail!("Session '{}' is headless and does not take messages", name);
+        }
+        tmux::send_keys(name, message)?;
+        // The session is working again, so its previous completion no longer applies
+        if let Some(mut record) = store::load(name)?
+            && record.completed_at.is_some()
+        {
+            record.completed_at = None;
+            store::save(&record)?;
+        }
+        Ok(())
+    }
+
+    /// Stop a live session, recording the changes it made
+    pub fn stop(&self, name: &str) -> Result<()> {
+        self.require_live(name)?;
+        session::record_completion(name);
+        let adopted = store::load(name)?.is_some_and(|r| r.adopted_from.is_some());
+        if adopted {
+            // Only the pane is ccx's; the tmux session around it is the user's
+            tmux::remove_agent_pane(name)?;
+            session::record_closed(name);
+            Ok(())
+        } else {
+            tmux::kill_session(name)
+        }
+    }
+
+    /// Stop a session and every live session started below it, children before their
+    /// parents. Returns the sessions stopped.
+    pub fn stop_recursive(&self, name: &str) -> Result<Vec<String>> {
+        let mut stopped = Vec::new();
+        for session in session::descendants(name)?
+            .into_iter()
+            .chain([name.to_string()])
+        {
+            if self.require_live(&session).is_ok() {
+                self.stop(&session)?;
+                stopped.push(session);
+            }
+        }
+        if stopped.is_empty() {
+            anyhow::bail!("Session '{}' does not exist", name);
+        }
+        Ok(stopped)
+    }
+
+    /// Sessions started by a session's agent, oldest first
+    pub fn children(&self, name: &str) -> Result<Vec<Session>> {
+        session::children(name)?
+            .iter()
+            .map(|record| self.get(&record.name))
+            .collect()
+    }
+
+    /// The last `lines` non-empty lines of a live session's pane
+    pub fn output(&self, name: &str, lines: usize) -> Result<String> {
+        self.require_live(name)?;
+        let content = tmux::capture_pane(name, lines as i32)?;
+        let non_empty: Vec<&str> = content.lines().filter(|l| !l.trim().is_empty()).collect();
+        let start = non_empty.len().saturating_sub(lines);
+        Ok(non_empty[start..].join("\n"))
+    }
+
+    /// Block until a session is done, failed or exited
+    pub fn wait(&self, name: &str, interval: Duration) -> Result<SessionStatus> {
+        loop {
+            let session = self.get(name)?;
+            if session.status.is_finished() {
+                return Ok(session.status);
+            }
+            thread::sleep(interval);
+        }
+    }
+
+    /// Poll sessions every `interval`, yielding an event for each change. The first
+    /// poll reports every current session as started.
+    pub fn watch(&self, interval: Duration) -> Events {
+        Events {
+            manager: self.clone(),
+            interval,
+            seen: HashMap::new(),
+            pending: Vec::new(),
+            polled: false,
+        }
+    }
+
+    fn require_live(&self, name: &str) -> Result<()> {
+        if !session::is_live(name) {
+            anyhow::bail!("Session '{}' does not exist", name);
+        }
+        Ok(())
+    }
+}
+
+/// Iterator over session changes, from [`SessionManager::watch`]
+pub struct Events {
+    manager: SessionManager,
+    interval: Duration,
+    seen: HashMap<String, SessionStatus>,
+    /// Events found by the last poll, not yet returned (in reverse order)
+    pending: Vec<Event>,
+    polled: bool,
+}
+
+impl Events {
+    fn poll(&mut self) -> Result<()> {
+        let sessions = self.manager.list()?;
+        let mut events = diff(&self.seen, &sessions);
+        self.seen = sessions.into_iter().map(|s| (s.name, s.status)).collect();
+        events.reverse();
+        self.pending = events;
+        Ok(())
+    }
+}
+
+impl Iterator for Events {
+    type Item = Result<Event>;
+
+    fn next(&mut self) -> Option<Result<Event>> {
+        loop {
+            if let Some(event) = self.pending.pop() {
+                return Some(Ok(event));
+            }
+            if self.polled {
+                thread::sleep(self.interval);
+            }
+            self.polled = true;
+            if let Err(e) = self.poll() {
+                return Some(Err(e));
+            }
+        }
+    }
+}
+
+/// Events turning the `seen` statuses into `sessions`
+fn diff(seen: &HashMap<String, SessionStatus>, sessions: &[Session]) -> Vec<Event> {
+    let mut events = Vec::new();
+    for session in sessions {
+        match seen.get(&session.name) {
+            None => events.push(Event::Started {
+                session: session.name.clone(),
+                status: session.status,
+            }),
+            Some(from) if *from != session.status => events.push(Event::StatusChanged {
+                session: session.name.clone(),
+                from: *from,
+                to: session.status,
+            }),
+            Some(_) => {}
+        }
+    }
+    let mut removed: Vec<&String> = seen
+        .keys()
+        .filter(|name| !sessions.iter().any(|s| &s.name == *name))
+        .collect();
+    removed.sort();
+    events.extend(removed.into_iter().map(|name| Event::Removed {
+        session: name.clone(),
+    }));
+    events
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn session(name: &str, status: SessionStatus) -> Session {
+        Session {
+            name: name.to_string(),
+            status,
+            live: true,
+            attached: false,
+            title: None,
+            cwd: None,
+            label: None,
+            tags: Vec::new(),
+            parent: None,
+            created_at: None,
+        }
+    }
+
+    #[test]
+    fn test_status_from_record() {
+        let mut record = SessionRecord {
+            completed_at: Some(1),
+            ..Default::default()
+        };
+        assert_eq!(SessionStatus::from_record(&record), SessionStatus::Done);
+        record.exit_code = Some(1);
+        assert_eq!(
+            SessionStatus::from_record(&record),
+            SessionStatus::Exited { code: 1 }
+        );
+        record.completed_at = None;
+        record.exit_code = None;
+        assert_eq!(SessionStatus::from_record(&record), SessionStatus::Failed);
+    }
+
+    #[test]
+    fn test_status_serializes_with_state_tag() {
+        assert_eq!(
+            serde_json::to_string(&SessionStatus::Exited { code: 2 }).unwrap(),
+            r#"{"state":"exited","code":2}"#
+        );
+        assert_eq!(
+            serde_json::to_string(&SessionStatus::WaitingForInput).unwrap(),
+            r#"{"state":"waiting_for_input"}"#
+        );
+    }
+
+    #[test]
+    fn test_diff() {
+        let seen = HashMap::from([
+            ("a".to_string(), SessionStatus::Working),
+            ("b".to_string(), SessionStatus::Working),
+            ("c".to_string(), SessionStatus::Working),
+        ]);
+        let sessions = vec![
+            session("a", SessionStatus::Working),
+            session("b", SessionStatus::Done),
+            session("d", SessionStatus::Starting),
+        ];
+        assert_eq!(
+            diff(&seen, &sessions),
+            vec![
+                Event::StatusChanged {
+                    session: "b".to_string(),
+                    from: SessionStatus::Working,
+                    to: SessionStatus::Done,
+                },
+                Event::Started {
+                    session: "d".to_string(),
+                    status: SessionStatus::Starting,
+                },
+                Event::Removed {
+                    session: "c".to_string(),
+                },
+            ]
+        );
+    }
+}
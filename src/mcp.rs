@@ -0,0 +1,380 @@
+use crate::{SessionManager, server};
+use anyhow::{Context, Result};
+use serde::Deserialize;
+use serde_json::{Value, json};
+use std::io::{self, BufRead, Write};
+
+/// Protocol revisions this server speaks, newest last
+const PROTOCOL_VERSIONS: &[&str] = &["2024-11-05", "2025-03-26", "2025-06-18"];
+
+// JSON-RPC error codes
+const PARSE_ERROR: i64 = -32700;
+const INVALID_REQUEST: i64 = -32600;
+const METHOD_NOT_FOUND: i64 = -32601;
+const INVALID_PARAMS: i64 = -32602;
+
+/// Lines of pane output returned by read_output when not given
+const DEFAULT_OUTPUT_LINES: usize = 20;
+
+/// A JSON-RPC error response
+#[derive(Debug)]
+struct RpcError {
+    code: i64,
+    message: String,
+}
+
+impl RpcError {
+    fn new(code: i64, message: impl Into<String>) -> RpcError {
+        RpcError {
+            code,
+            message: message.into(),
+        }
+    }
+}
+
+/// Serve the Model Context Protocol on stdin and stdout, one JSON-RPC message per line,
+/// until stdin closes
+pub fn serve() -> Result<()> {
+    let manager = SessionManager::new();
+    let mut stdout = io::stdout().lock();
+    for line in io::stdin().lock().lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        if let Some(response) = handle_message(&manager, &line) {
+            writeln!(stdout, "{}", response)?;
+            stdout.flush()?;
+        }
+    }
+    Ok(())
+}
+
+/// The response to one message, or None for notifications and responses
+fn handle_message(manager: &SessionManager, line: &str) -> Option<Value> {
+    let message: Value = match serde_json::from_str(line) {
+        Ok(message) => message,
+        Err(e) => {
+            return Some(error_response(
+                Value::Null,
+                RpcError::new(PARSE_ERROR, e.to_string()),
+            ));
+        }
+    };
+    let id = message.get("id").cloned();
+    let Some(method) = message.get("method").and_then(Value::as_str) else {
+        // A response to a request we never send, or garbage
+        return id.map(|id| error_response(id, RpcError::new(INVALID_REQUEST, "Missing method")));
+    };
+    let params = message.get("params").cloned().unwrap_or_else(|| json!({}));
+    let result = dispatch(manager, method, params);
+    // Notifications get no response
+    let id = id?;
+    Some(match result {
+        Ok(result) => json!({ "jsonrpc": "2.0", "id": id, "result": result }),
+        Err(error) => error_response(id, error),
+    })
+}
+
+fn error_response(id: Value, error: RpcError) -> Value {
+    json!({
+        "jsonrpc": "2.0",
+        "id": id,
+        "error": { "code": error.code, "message": error.message },
+    })
+}
+
+fn dispatch(manager: &SessionManager, method: &str, params: Value) -> Result<Value, RpcError> {
+    match method {
+        "initialize" => Ok(initialize(&params)),
+        "ping" => Ok(json!({})),
+        "tools/list" => Ok(json!({ "tools": tools() })),
+        "tools/call" => call_tool(manager, params),
+        _ if method.starts_with("notifications/") => Ok(Value::Null),
+        _ => Err(RpcError::new(
+            METHOD_NOT_FOUND,
+            format!("Unknown method '{}'", method),
+        )),
+    }
+}
+
+fn initialize(params: &Value) -> Value {
+    let requested = params.get("protocolVersion").and_then(Value::as_str);
+    let version = requested
+        .filter(|v| PROTOCOL_VERSIONS.contains(v))
+        .unwrap_or(PROTOCOL_VERSIONS[PROTOCOL_VERSIONS.len() - 1]);
+    json!({
+        "protocolVersion": version,
+        "capabilities": { "tools": {} },
+        "serverInfo": { "name": "ccx", "version": env!("CARGO_PKG_VERSION") },
+        "instructions": "Start background Claude Code sessions with start_session, poll them \
+            with get_status until their status is done, failed or exited, and read their \
+            final_message.",
+    })
+}
+
+fn session_schema(extra: Value) -> Value {
+    let mut schema = json!({
+        "type": "object",
+        "properties": {
+            "session": { "type": "string", "description": "Session name, e.g. ccx-1a2b3c4d" },
+        },
+        "required": ["session"],
+    });
+    if let Value::Object(extra) = extra {
+        for (key, value) in extra {
+            schema["properties"][&key] = value;
+        }
+    }
+    schema
+}
+
+fn tools() -> Value {
+    let strings = json!({ "type": "array", "items": { "type": "string" } });
+    let mut send_schema = session_schema(json!({ "message": { "type": "string" } }));
+    send_schema["required"] = json!(["session", "message"]);
+    json!([
+        {
+            "name": "start_session",
+            "description": "Start a background Claude Code session. Returns once the agent \
+                is running; poll get_status until it finishes.",
+            "inputSchema": {
+                "type": "object",
+                "properties": {
+                    "prompt": { "type": "string", "description": "The task for the session" },
+                    "template": { "type": "string", "description": "Prompt template name" },
+                    "vars": {
+                        "type": "object",
+                        "additionalProperties": { "type": "string" },
+                        "description": "Template variables",
+                    },
+                    "cwd": { "type": "string", "description": "Working directory" },
+                    "profile": { "type": "string", "description": "Profile from ~/.ccx/config.toml" },
+                    "tags": strings,
+                    "model": { "type": "string", "description": "Model alias or model id" },
+                    "max_turns": {
+                        "type": "integer",
+                        "minimum": 1,
+                        "description": "Turn limit; headless sessions only",
+                    },
+                    "allowed_tools": strings,
+                    "append_system_prompt": { "type": "string" },
+                    "on_conflict": {
+                        "type": "string",
+                        "enum": ["warn", "refuse", "queue", "worktree"],
+                        "description": "What to do if another session works in the same directory",
+                    },
+                    "review": { "type": "string", "description": "Reviewer prompt run on completion" },
+                    "headless": {
+                        "type": "boolean",
+                        "description": "Run in print mode, recording an exact result",
+                    },
+                },
+            },
+        },
+        {
+            "name": "list_sessions",
+            "description": "List live sessions and sessions that ended in the last day",
+            "inputSchema": { "type": "object", "properties": {} },
+        },
+        {
+            "name": "get_status",
+            "description": "Status of one session, with its final message or headless \
+                result once it has one",
+            "inputSchema": session_schema(json!({})),
+        },
+        {
+            "name": "read_output",
+            "description": "The last lines of a live session's terminal",
+            "inputSchema": session_schema(json!({
+                "lines": { "type": "integer", "minimum": 1, "default": DEFAULT_OUTPUT_LINES },
+            })),
+        },
+        {
+            "name": "send_message",
+            "description": "Type a message into a live session and submit it",
+            "inputSchema": send_schema,
+        },
+        {
+            "name": "stop_session",
+            "description": "Stop a live session, recording the changes it made",
+            "inputSchema": session_schema(json!({
+                "recursive": {
+                    "type": "boolean",
+                    "default": false,
+                    "description": "Also stop the sessions it started, and theirs",
+                },
+            })),
+        },
+    ])
+}
+
+#[derive(Deserialize)]
+struct SessionArgs {
+    session: String,
+}
+
+#[derive(Deserialize)]
+struct OutputArgs {
+    session: String,
+    lines: Option<usize>,
+}
+
+#[derive(Deserialize)]
+struct StopArgs {
+    session: String,
+    #[serde(default)]
+    recursive: bool,
+}
+
+#[derive(Deserialize)]
+struct SendArgs {
+    session: String,
+    message: String,
+}
+
+fn args<T: serde::de::DeserializeOwned>(arguments: Value) -> Result<T> {
+    serde_json::from_value(arguments).context("Invalid arguments")
+}
+
+fn run_tool(manager: &SessionManager, name: &str, arguments: Value) -> Result<Value> {
+    match name {
+        "start_session" => Ok(serde_json::to_value(server::start(manager, arguments)?)?),
+        "list_sessions" => Ok(json!({ "sessions": manager.list()? })),
+        "get_status" => {
+            let SessionArgs { session } = args(arguments)?;
+            let mut status = serde_json::to_value(manager.get(&session)?)?;
+            if let Ok(record) = manager.record(&session) {
+                if let Some(message) = record.final_message {
+                    status["final_message"] = json!(message);
+                }
+                if let Some(result) = record.result {
+                    status["result"] = serde_json::to_value(result)?;
+                }
+            }
+            Ok(status)
+        }
+        "read_output" => {
+            let OutputArgs { session, lines } = args(arguments)?;
+            let output = manager.output(&session, lines.unwrap_or(DEFAULT_OUTPUT_LINES))?;
+            Ok(json!({ "session": session, "output": output }))
+        }
+        "send_message" => {
+            let SendArgs { session, message } = args(arguments)?;
+            manager.send(&session, &message)?;
+            Ok(json!({ "session": session, "sent": true }))
+        }
+        "stop_session" => {
+            let StopArgs { session, recursive } = args(arguments)?;
+            let stopped = match recursive {
+                true => manager.stop_recursive(&session)?,
+                false => {
+                    manager.stop(&session)?;
+                    vec![session]
+                }
+            };
+            Ok(json!({ "stopped": stopped }))
+        }
+        _ => unreachable!("tool names are checked by call_tool"),
+    }
+}
+
+/// Run a tool. Failures of the tool itself are reported in the result, so the
+/// calling agent sees them and can react.
+fn call_tool(manager: &SessionManager, params: Value) -> Result<Value, RpcError> {
+    let name = params
+        .get("name")
+        .and_then(Value::as_str)
+        .ok_or_else(|| RpcError::new(INVALID_PARAMS, "Missing tool name"))?;
+    if !tools()
+        .as_array()
+        .is_some_and(|tools| tools.iter().any(|t| t["name"] == name))
+    {
+        return Err(RpcError::new(
+            INVALID_PARAMS,
+            format!("Unknown tool '{}'", name),
+        ));
+    }
+    let arguments = params
+        .get("arguments")
+        .cloned()
+        .unwrap_or_else(|| json!({}));
+    Ok(match run_tool(manager, name, arguments) {
+        Ok(value) => json!({
+            "content": [{ "type": "text", "text": serde_json::to_string_pretty(&value).unwrap_or_default() }],
+            "structuredContent": value,
+            "isError": false,
+        }),
+        Err(e) => json!({
+            "content": [{ "type": "text", "text": format!("{:#}", e) }],
+            "isError": true,
+        }),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request(method: &str, params: Value) -> Value {
+        let line = json!({ "jsonrpc": "2.0", "id": 1, "method": method, "params": params });
+        handle_message(&SessionManager::new(), &line.to_string()).unwrap()
+    }
+
+    #[test]
+    fn test_initialize() {
+        let response = request("initialize", json!({ "protocolVersion": "2025-03-26" }));
+        assert_eq!(response["id"], 1);
+        assert_eq!(response["result"]["protocolVersion"], "2025-03-26");
+        assert_eq!(response["result"]["serverInfo"]["name"], "ccx");
+
+        let response = request("initialize", json!({ "protocolVersion": "1999-01-01" }));
+        assert_eq!(response["result"]["protocolVersion"], "2025-06-18");
+    }
+
+    #[test]
+    fn test_tools_list() {
+        let response = request("tools/list", json!({}));
+        let tools = response["result"]["tools"].as_array().unwrap();
+        let names: Vec<&str> = tools.iter().map(|t| t["name"].as_str().unwrap()).collect();
+        assert_eq!(
+            names,
+            [
+                "start_session",
+                "list_sessions",
+                "get_status",
+                "read_output",
+                "send_message",
+                "stop_session"
+            ]
+        );
+        for tool in tools {
+            assert_eq!(tool["inputSchema"]["type"], "object");
+        }
+        let send = &tools[4]["inputSchema"];
+        assert_eq!(send["required"], json!(["session", "message"]));
+        assert_eq!(send["properties"]["message"]["type"], "string");
+    }
+
+    #[test]
+    fn test_errors() {
+        let response = request("tools/call", json!({ "name": "rm_rf" }));
+        assert_eq!(response["error"]["code"], INVALID_PARAMS);
+
+        // Bad arguments are a tool failure the agent can see, not a protocol error
+        let response = request(
+            "tools/call",
+            json!({ "name": "send_message", "arguments": { "session": "x" } }),
+        );
+        assert_eq!(response["result"]["isError"], true);
+
+        let response = request("resources/list", json!({}));
+        assert_eq!(response["error"]["code"], METHOD_NOT_FOUND);
+
+        let response = handle_message(&SessionManager::new(), "{not json").unwrap();
+        assert_eq!(response["error"]["code"], PARSE_ERROR);
+
+        let notification = json!({ "jsonrpc": "2.0", "method": "notifications/initialized" });
+        assert!(handle_message(&SessionManager::new(), &notification.to_string()).is_none());
+    }
+}
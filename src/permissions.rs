@@ -0,0 +1,441 @@
+use crate::{session, tmux};
+use serde::{Deserialize, Serialize};
+use std::time::{Duration, Instant};
+
+/// How a session's tool permission prompts are handled
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum PermissionMode {
+    /// Launch with `--dangerously-skip-permissions`, so no prompts are shown
+    #[default]
+    Skip,
+    /// Launch without it and answer prompts from the allow and deny lists,
+    /// escalating anything else
+    Auto,
+}
+
+/// `[permissions]` settings: the mode and the rules used to answer prompts.
+/// Rules use Claude Code's syntax: `Tool` or `Tool(pattern)`, where `*` in the
+/// pattern matches anything, e.g. `Read`, `Bash(cargo test*)`, `Bash(rm *)`.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PermissionPolicy {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub mode: Option<PermissionMode>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub allow: Vec<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub deny: Vec<String>,
+}
+
+/// A permission prompt found in a session's pane
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PermissionRequest {
+    /// Tool name as used in rules, e.g. `Bash`, `Edit`, `WebFetch`
+    pub tool: String,
+    /// What the tool will act on: the command, file path or URL
+    pub detail: String,
+    /// The dialog may show more than `detail`: a command continuing on further lines,
+    /// or a line wrapped at the dialog's width
+    pub truncated: bool,
+}
+
+impl std::fmt::Display for PermissionRequest {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let more = if self.truncated { "…" } else { "" };
+        write!(f, "{}({}{})", self.tool, self.detail, more)
+    }
+}
+
+/// What to do with a permission prompt
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Decision {
+    /// Approve, citing the matching allow rule
+    Allow(String),
+    /// Reject, citing the matching deny rule
+    Deny(String),
+    /// No rule matched; ask a person
+    Escalate,
+}
+
+/// Map a dialog heading to the tool name used in rules
+fn tool_name(heading: &str) -> String {
+    match heading {
+        "Bash command" => "Bash".to_string(),
+        "Edit file" => "Edit".to_string(),
+        "Create file" => "Write".to_string(),
+        "Read file" => "Read".to_string(),
+        "Fetch" => "WebFetch".to_string(),
+        "Web Search" => "WebSearch".to_string(),
+        other => other.split_whitespace().next().unwrap_or(other).to_string(),
+    }
+}
+
+/// Find a permission prompt in pane content. Claude Code draws these as a box with a
+/// heading naming the tool, the command or file, then "Do you want to ...?" and a
+/// numbered menu.
+pub fn parse_prompt(content: &str) -> Option<PermissionRequest> {
+    let raw: Vec<&str> = content.lines().map(str::trim).collect();
+    let lines: Vec<&str> = raw
+        .iter()
+        .map(|l| l.trim_matches(['│', '|']).trim())
+        .collect();
+    let question = lines
+        .iter()
+        .rposition(|l| l.starts_with("Do you want to"))?;
+    // The menu must still be showing, or the prompt was already answered
+    if !lines[question..].iter().any(|l| l.contains("1. Yes")) {
+        return None;
+    }
+
+    // The dialog starts after the nearest box border above the question
+    let start = lines[..question]
+        .iter()
+        .rposition(|l| l.starts_with('╭') || l.starts_with('─'))
+        .map_or(0, |i| i + 1);
+    let body: Vec<usize> = (start..question)
+        .filter(|&i| !lines[i].is_empty())
+        .collect();
+    let (&heading, rest) = body.split_first()?;
+    let tool = tool_name(lines[heading]);
+    let Some(&detail) = rest.first() else {
+        return Some(PermissionRequest {
+            tool,
+            detail: String::new(),
+            truncated: false,
+        });
+    };
+
+    // A line reaching the box's right edge was probably wrapped
+    let wrapped = start.checked_sub(1).is_some_and(|border| {
+        let width = raw[border].chars().count().saturating_sub(2);
+        let used = raw[detail]
+            .trim_start_matches('│')
+            .trim_end_matches('│')
+            .trim_end()
+            .chars()
+            .count();
+        raw[detail].ends_with('│') && used + 1 >= width && used <= width
+    });
+    // A command shows on its own lines, followed by one line describing it
+    let continued = tool == "Bash"
+        && (rest.len() > 2
+            || ["\\", "&&", "||", "|", ";", "&", "(", "{"]
+                .iter()
+                .any(|end| lines[detail].ends_with(end)));
+    Some(PermissionRequest {
+        tool,
+        detail: lines[detail].to_string(),
+        truncated: wrapped || continued,
+    })
+}
+
+/// The simple commands in a shell command line, split at `&&`, `||`, `;`, `|`, `&`
+/// and newlines (but not redirections like `2>&1`). Quotes are not understood, so a
+/// quoted separator splits too; that can only make a rule match less.
+fn split_commands(command: &str) -> Vec<&str> {
+    let bytes = command.as_bytes();
+    let mut parts = Vec::new();
+    let mut start = 0;
+    for (i, &byte) in bytes.iter().enumerate() {
+        let redirect = byte == b'&'
+            && ((i > 0 && matches!(bytes[i - 1], b'>' | b'<')) || bytes.get(i + 1) == Some(&b'>'));
+        if matches!(byte, b'\n' | b';' | b'|' | b'&') && !redirect {
+            parts.push(&command[start..i]);
+            start = i + 1;
+        }
+    }
+    parts.push(&command[start..]);
+    parts
+        .into_iter()
+        .map(str::trim)
+        .filter(|part| !part.is_empty())
+        .collect()
+}
+
+/// Whether a command line substitutes another command's output, which no allow rule
+/// can vouch for
+fn has_substitution(command: &str) -> bool {
+    command.contains("$(") || command.contains('`') || command.contains("<(")
+}
+
+/// Whether `text` matches a glob where `*` matches any run of characters
+fn glob_match(pattern: &str, text: &str) -> bool {
+    let parts: Vec<&str> = pattern.split('*').collect();
+    if parts.len() == 1 {
+        return pattern == text;
+    }
+    let (first, last) = (parts[0], parts[parts.len() - 1]);
+    if !text.starts_with(first) || !text[first.len()..].ends_with(last) {
+        return false;
+    }
+    let mut rest = &text[first.len()..text.len() - last.len()];
+    for part in &parts[1..parts.len() - 1] {
+        match rest.find(part) {
+            Some(i) => rest = &rest[i + part.len()..],
+            None => return false,
+        }
+    }
+    true
+}
+
+/// Whether a `Tool` or `Tool(pattern)` rule covers a tool acting on `detail`
+fn rule_matches(rule: &str, tool: &str, detail: &str) -> bool {
+    match rule.split_once('(') {
+        Some((rule_tool, pattern)) => {
+            rule_tool == tool && glob_match(pattern.trim_end_matches(')'), detail)
+        }
+        None => rule == tool,
+    }
+}
+
+impl PermissionPolicy {
+    /// Deny rules win over allow rules; anything unmatched is escalated. A Bash
+    /// command is denied if any of its simple commands is, and allowed only if every
+    /// one of them is. A request that may be truncated is never allowed.
+    pub fn decide(&self, request: &PermissionRequest) -> Decision {
+        let parts = match request.tool.as_str() {
+            "Bash" => split_commands(&request.detail),
+            _ => vec![request.detail.as_str()],
+        };
+        let find = |rules: &[String], detail: &str| {
+            rules
+                .iter()
+                .find(|r| rule_matches(r, &request.tool, detail))
+                .cloned()
+        };
+        if let Some(rule) = std::iter::once(request.detail.as_str())
+            .chain(parts.iter().copied())
+            .find_map(|detail| find(&self.deny, detail))
+        {
+            return Decision::Deny(rule);
+        }
+        if request.truncated || (request.tool == "Bash" && has_substitution(&request.detail)) {
+            return Decision::Escalate;
+        }
+        let mut rules: Vec<String> = Vec::new();
+        for part in &parts {
+            let Some(rule) = find(&self.allow, part) else {
+                return Decision::Escalate;
+            };
+            if !rules.contains(&rule) {
+                rules.push(rule);
+            }
+        }
+        match rules.is_empty() {
+            // A request with no detail, e.g. a tool taking no argument
+            true => find(&self.allow, "").map_or(Decision::Escalate, Decision::Allow),
+            false => Decision::Allow(rules.join(", ")),
+        }
+    }
+}
+
+/// Watches a running session's pane and answers permission prompts
+pub struct Responder {
+    policy: PermissionPolicy,
+    /// The last prompt handled and when, so a dialog still on screen isn't answered
+    /// twice and an escalated one is reported once while it shows
+    last: Option<(PermissionRequest, Instant, Decision)>,
+}
+
+impl Responder {
+    pub fn new(policy: PermissionPolicy) -> Responder {
+        Responder { policy, last: None }
+    }
+
+    /// Answer or escalate the permission prompt showing in `session`, if any
+    pub fn check(&mut self, session: &str) {
+        let Ok(content) = tmux::capture_pane(session, 30) else {
+            return;
+        };
+        let Some(request) = parse_prompt(&content) else {
+            // The prompt went away, so the same request showing again is a new one
+            self.last = None;
+            return;
+        };
+        if let Some((last, at, decision)) = &self.last
+            && *last == request
+            && (*decision == Decision::Escalate || at.elapsed() < Duration::from_secs(10))
+        {
+            return;
+        }
+        let decision = self.policy.decide(&request);
+        self.last = Some((request.clone(), Instant::now(), decision.clone()));
+
+        match decision {
+            Decision::Allow(rule) => {
+                tmux::session_log(session, &format!("Allowed {} (rule {})", request, rule));
+                let _ = tmux::send_key(session, "Enter");
+            }
+            Decision::Deny(rule) => {
+                tmux::session_log(session, &format!("Denied {} (rule {})", request, rule));
+                let _ = tmux::send_key(session, "Escape");
+                let _ = tmux::send_keys(
+                    session,
+                    &format!(
+                        "ccx policy does not allow {} (rule {}). Continue without it.",
+                        request, rule
+                    ),
+                );
+            }
+            Decision::Escalate => {
+                tmux::set_waiting(session, &request.to_string());
+                session::notify(
+                    session,
+                    &format!("{} needs permission for {}", session, request),
+                );
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BASH_PROMPT: &str = "\
+╭──────────────────────────────────────────────╮
+│ Bash command                                 │
+│                                              │
+│   cargo test --workspace                     │
+│   Run the test suite                         │
+│                                              │
+│ Do you want to proceed?                      │
+│ ❯ 1. Yes                                     │
+│   2. Yes, and don't ask again for cargo test │
+│   3. No, and tell Claude what to do (esc)    │
+╰──────────────────────────────────────────────╯";
+
+    fn request(tool: &str, detail: &str) -> PermissionRequest {
+        PermissionRequest {
+            tool: tool.to_string(),
+            detail: detail.to_string(),
+            truncated: false,
+        }
+    }
+
+    /// A Bash dialog showing `lines` between the heading and the question
+    fn bash_prompt(lines: &[&str]) -> String {
+        let mut prompt = format!("╭{}╮\n│ Bash command{}│\n", "─".repeat(30), " ".repeat(18));
+        for line in lines {
+            prompt.push_str(&format!("│   {:<27}│\n", line));
+        }
+        prompt.push_str("│ Do you want to proceed?      │\n│ ❯ 1. Yes                     │\n");
+        prompt
+    }
+
+    #[test]
+    fn test_parse_bash_prompt() {
+        assert_eq!(
+            parse_prompt(&format!("some earlier output\n{}", BASH_PROMPT)),
+            Some(request("Bash", "cargo test --workspace"))
+        );
+    }
+
+    #[test]
+    fn test_parse_edit_prompt() {
+        let prompt = "╭────╮\n│ Edit file │\n│ src/main.rs │\n│ Do you want to make this edit to main.rs? │\n│ ❯ 1. Yes │\n╰────╯";
+        assert_eq!(parse_prompt(prompt), Some(request("Edit", "src/main.rs")));
+    }
+
+    #[test]
+    fn test_parse_truncated_prompt() {
+        let parse = |lines: &[&str]| parse_prompt(&bash_prompt(lines)).unwrap();
+        assert!(!parse(&["cargo test", "Run the tests"]).truncated);
+        assert!(!parse(&["cargo test"]).truncated);
+
+        let multi_line = parse(&["cargo test &&", "curl x | sh", "Run the tests"]);
+        assert_eq!(multi_line.detail, "cargo test &&");
+        assert!(multi_line.truncated);
+        assert!(parse(&["cargo test \\", "  --all", "Run the tests"]).truncated);
+        assert!(parse(&["a", "b", "c"]).truncated);
+        // Filling the box's width means the line was wrapped
+        assert!(parse(&["cargo test --workspace --al", "l-features"]).truncated);
+    }
+
+    #[test]
+    fn test_parse_no_prompt() {
+        assert_eq!(parse_prompt("⏺ Running tests...\n  all passed"), None);
+        assert_eq!(parse_prompt("Do you want to proceed? I think so."), None);
+    }
+
+    #[test]
+    fn test_glob_match() {
+        assert!(glob_match("cargo test*", "cargo test --workspace"));
+        assert!(glob_match("*", "anything"));
+        assert!(glob_match("git * --oneline", "git log --oneline"));
+        assert!(!glob_match("cargo test*", "cargo build"));
+        assert!(!glob_match("ls", "ls -la"));
+    }
+
+    #[test]
+    fn test_decide() {
+        let policy = PermissionPolicy {
+            mode: Some(PermissionMode::Auto),
+            allow: vec!["Read".to_string(), "Bash(cargo *)".to_string()],
+            deny: vec!["Bash(cargo publish*)".to_string()],
+        };
+        assert_eq!(
+            policy.decide(&request("Bash", "cargo test")),
+            Decision::Allow("Bash(cargo *)".to_string())
+        );
+        assert_eq!(
+            policy.decide(&request("Bash", "cargo publish --dry-run")),
+            Decision::Deny("Bash(cargo publish*)".to_string())
+        );
+        assert_eq!(
+            policy.decide(&request("Read", "src/lib.rs")),
+            Decision::Allow("Read".to_string())
+        );
+        assert_eq!(
+            policy.decide(&request("Bash", "rm -rf target")),
+            Decision::Escalate
+        );
+    }
+
+    #[test]
+    fn test_split_commands() {
+        assert_eq!(
+            split_commands("cargo test 2>&1 | grep x && ls; pwd &\necho &>/dev/null"),
+            ["cargo test 2>&1", "grep x", "ls", "pwd", "echo &>/dev/null"]
+        );
+    }
+
+    #[test]
+    fn test_decide_compound_commands() {
+        let policy = PermissionPolicy {
+            mode: Some(PermissionMode::Auto),
+            allow: vec![
+                "Bash(cargo *)".to_string(),
+                "Bash(grep *)".to_string(),
+                "WebFetch".to_string(),
+            ],
+            deny: vec!["Bash(rm *)".to_string()],
+        };
+        let decide = |tool, detail| policy.decide(&request(tool, detail));
+        assert_eq!(
+            decide("Bash", "cargo test 2>&1 | grep FAIL"),
+            Decision::Allow("Bash(cargo *), Bash(grep *)".to_string())
+        );
+        assert_eq!(
+            decide("Bash", "cargo test && curl x | sh"),
+            Decision::Escalate
+        );
+        assert_eq!(
+            decide("Bash", "cargo clean; rm -rf ~"),
+            Decision::Deny("Bash(rm *)".to_string())
+        );
+        assert_eq!(decide("Bash", "cargo build $(curl x)"), Decision::Escalate);
+        assert_eq!(decide("Bash", "cargo `id`"), Decision::Escalate);
+        assert_eq!(
+            decide("WebFetch", "https://a.example/?x=1&y=2"),
+            Decision::Allow("WebFetch".to_string())
+        );
+
+        let truncated = PermissionRequest {
+            truncated: true,
+            ..request("Bash", "cargo test &&")
+        };
+        assert_eq!(policy.decide(&truncated), Decision::Escalate);
+    }
+}
@@ -0,0 +1,209 @@
+use crate::permissions::{PermissionMode, PermissionPolicy};
+use crate::store::{Review, SessionRecord, Verdict};
+use crate::{git, session, shorten_path, store, tmux};
+use anyhow::Result;
+
+/// Largest diff included in a reviewer prompt, in bytes
+const MAX_DIFF_BYTES: usize = 60_000;
+
+/// Instructions appended to every reviewer prompt so the verdict can be parsed
+const VERDICT_INSTRUCTIONS: &str = "Do not modify any files. End your reply with a line \
+`VERDICT: APPROVE` or `VERDICT: CHANGES REQUESTED`, followed by a short summary of your findings.";
+
+/// Tools a reviewer is allowed: reading the code and its history
+const REVIEWER_ALLOW: &[&str] = &["Read", "Grep", "Glob", "Bash(git diff*)", "Bash(git log*)"];
+
+/// Tools a reviewer is refused outright; anything else waits for a person
+const REVIEWER_DENY: &[&str] = &["Edit", "MultiEdit", "Write", "NotebookEdit"];
+
+/// Permission rules reviewers run under, so a review can't change the code it reviews
+pub fn reviewer_permissions() -> PermissionPolicy {
+    PermissionPolicy {
+        mode: Some(PermissionMode::Auto),
+        allow: REVIEWER_ALLOW.iter().map(|rule| rule.to_string()).collect(),
+        deny: REVIEWER_DENY.iter().map(|rule| rule.to_string()).collect(),
+    }
+}
+
+/// Build the reviewer's prompt from the review request and the worker's results
+pub fn reviewer_prompt(review_prompt: &str, worker: &SessionRecord) -> String {
+    let mut prompt = format!(
+        "{}\n\nYou are reviewing the work another session did in {}.\n",
+        review_prompt.trim(),
+        shorten_path(&worker.cwd)
+    );
+
+    prompt.push_str("\n## Original task\n\n");
+    prompt.push_str(worker.prompt.trim());
+    prompt.push('\n');
+
+    if let Some(message) = &worker.final_message {
+        prompt.push_str("\n## Worker's final message\n\n");
+        prompt.push_str(message.trim());
+        prompt.push('\n');
+    }
+
+    if let Some(changes) = &worker.git_end
+        && !changes.commits.is_empty()
+    {
+        prompt.push_str("\n## Commits\n\n");
+        for commit in &changes.commits {
+            prompt.push_str(&format!("- {}\n", commit));
+        }
+    }
+
+    if let Some(start) = &worker.git_start {
+        let diff = git::diff_since(start, false).unwrap_or_default();
+        if diff.trim().is_empty() {
+            prompt.push_str("\n## Diff\n\nThe worker made no changes.\n");
+        } else {
+            prompt.push_str("\n## Diff\n\n```diff\n");
+            prompt.push_str(truncate(&diff, MAX_DIFF_BYTES));
+            if diff.len() > MAX_DIFF_BYTES {
+                prompt.push_str("\n... (diff truncated, run `git diff` for the rest)");
+            }
+            prompt.push_str("\n```\n");
+        }
+    }
+
+    prompt.push('\n');
+    prompt.push_str(VERDICT_INSTRUCTIONS);
+    prompt
+}
+
+/// The longest prefix of `s` at most `max` bytes long, cut at a character boundary
+fn truncate(s: &str, max: usize) -> &str {
+    if s.len() <= max {
+        return s;
+    }
+    let mut end = max;
+    while !s.is_char_boundary(end) {
+        end -= 1;
+    }
+    &s[..end]
+}
+
+/// Read the verdict from the last `VERDICT:` line of a reviewer's message
+pub fn parse_verdict(message: &str) -> Verdict {
+    let Some(line) = message
+        .lines()
+        .rev()
+        .find(|l| l.to_uppercase().contains("VERDICT:"))
+    else {
+        return Verdict::Unclear;
+    };
+    let upper = line.to_uppercase();
+    let verdict = &upper[upper.find("VERDICT:").unwrap_or(0)..];
+    if verdict.contains("CHANGES") || verdict.contains("REJECT") {
+        Verdict::ChangesRequested
+    } else if verdict.contains("APPROVE") {
+        Verdict::Approved
+    } else {
+        Verdict::Unclear
+    }
+}
+
+/// Launch the reviewer for a completed worker session and link it on the worker's record
+pub fn start_reviewer(worker: &SessionRecord, review: &Review) -> Result<String> {
+    let reviewer = session::start(session::StartOptions {
+        prompt: reviewer_prompt(&review.prompt, worker),
+        cwd: Some(worker.cwd.clone()),
+        label: Some("review".to_string()),
+        tags: worker.tags.clone(),
+        reviews: Some(worker.name.clone()),
+        no_verify: true,
+        permissions: Some(PermissionMode::Auto),
+        permission_rules: Some(reviewer_permissions()),
+        ..Default::default()
+    })?;
+
+    if let Some(mut record) = store::load(&worker.name)? {
+        record.review = Some(Review {
+            session: Some(reviewer.clone()),
+            ..review.clone()
+        });
+        store::save(&record)?;
+    }
+    tmux::session_log(&worker.name, &format!("Started reviewer {}", reviewer));
+    Ok(reviewer)
+}
+
+/// Record a completed reviewer's verdict on the session it reviewed
+pub fn record_verdict(reviewer: &SessionRecord, worker: &str) -> Result<()> {
+    let message = reviewer.final_message.clone().unwrap_or_default();
+    let verdict = parse_verdict(&message);
+
+    if let Some(mut record) = store::load(worker)? {
+        let review = record.review.get_or_insert_with(Review::default);
+        review.session = Some(reviewer.name.clone());
+        review.verdict = Some(verdict);
+        review.notes = reviewer.final_message.clone();
+        store::save(&record)?;
+    }
+    tmux::session_log(worker, &format!("Review by {}: {}", reviewer.name, verdict));
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::permissions::{Decision, PermissionRequest};
+
+    #[test]
+    fn test_reviewer_permissions() {
+        let policy = reviewer_permissions();
+        let decide = |tool: &str, detail: &str| {
+            policy.decide(&PermissionRequest {
+                tool: tool.to_string(),
+                detail: detail.to_string(),
+                truncated: false,
+            })
+        };
+        assert!(matches!(
+            decide("Bash", "git diff HEAD~1"),
+            Decision::Allow(_)
+        ));
+        assert!(matches!(decide("Read", "src/lib.rs"), Decision::Allow(_)));
+        assert!(matches!(decide("Edit", "src/lib.rs"), Decision::Deny(_)));
+        assert_eq!(decide("Bash", "git diff && git push"), Decision::Escalate);
+    }
+
+    #[test]
+    fn test_parse_verdict() {
+        assert_eq!(
+            parse_verdict("Looks good.\n\nVERDICT: APPROVE\nClean change."),
+            Verdict::Approved
+        );
+        assert_eq!(
+            parse_verdict("**Verdict: changes requested** - tests are missing"),
+            Verdict::ChangesRequested
+        );
+        assert_eq!(
+            parse_verdict("VERDICT: APPROVE\n...\nVERDICT: REJECT"),
+            Verdict::ChangesRequested
+        );
+        assert_eq!(parse_verdict("I approve of this."), Verdict::Unclear);
+    }
+
+    #[test]
+    fn test_reviewer_prompt() {
+        let worker = SessionRecord {
+            name: "ccx-1".to_string(),
+            cwd: "/tmp/app".to_string(),
+            prompt: "fix the parser".to_string(),
+            final_message: Some("Fixed the off-by-one.".to_string()),
+            ..Default::default()
+        };
+        let prompt = reviewer_prompt("Check for missing tests", &worker);
+        assert!(prompt.starts_with("Check for missing tests"));
+        assert!(prompt.contains("fix the parser"));
+        assert!(prompt.contains("Fixed the off-by-one."));
+        assert!(prompt.ends_with(VERDICT_INSTRUCTIONS));
+    }
+
+    #[test]
+    fn test_truncate() {
+        assert_eq!(truncate("héllo", 2), "h");
+        assert_eq!(truncate("hello", 10), "hello");
+    }
+}
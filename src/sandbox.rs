@@ -0,0 +1,367 @@
+use crate::config::{self, expand_tilde};
+use crate::git;
+use anyhow::{Context, Result};
+use serde::Deserialize;
+use std::path::{Path, PathBuf};
+
+/// What confines a sandboxed session
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Backend {
+    /// bubblewrap (`bwrap`): read-only root, masked home, writable working directory
+    #[default]
+    Bwrap,
+    /// A rootless container (podman or docker) with only the working directory mounted
+    Container,
+}
+
+/// Network access inside the sandbox
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Network {
+    /// Share the host network (Claude Code needs the API)
+    #[default]
+    Host,
+    /// No network at all
+    None,
+}
+
+/// `[sandboxes.<name>]` in ~/.ccx/config.toml
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SandboxProfile {
+    pub backend: Backend,
+    pub network: Network,
+    /// Extra paths mounted writable (`~` is expanded)
+    pub writable: Vec<String>,
+    /// Extra paths mounted read-only; with bwrap everything outside home already is
+    pub readonly: Vec<String>,
+    /// Container image with claude installed (container backend)
+    pub image: Option<String>,
+    /// Container runtime (default: podman)
+    pub runtime: Option<String>,
+}
+
+/// Look up a sandbox profile. `default` is built in: bwrap with the host network.
+pub fn profile(name: &str) -> Result<SandboxProfile> {
+    match config::load_global()?.sandboxes.remove(name) {
+        Some(profile) => Ok(profile),
+        None if name == "default" => Ok(SandboxProfile::default()),
+        None => anyhow::bail!("Sandbox profile '{}' not found in ~/.ccx/config.toml", name),
+    }
+}
+
+/// Find `program` on PATH
+pub(crate) fn find_on_path(program: &str) -> Option<PathBuf> {
+    let paths = std::env::var_os("PATH")?;
+    std::env::split_paths(&paths)
+        .map(|dir| dir.join(program))
+        .find(|path| path.is_file())
+}
+
+/// Directories holding the claude executable, so it stays visible when it is installed
+/// under the masked home directory
+fn claude_install(home: &Path) -> Vec<String> {
+    let Some(path) = find_on_path("claude") else {
+        return Vec::new();
+    };
+    let target = path.canonicalize().unwrap_or_else(|_| path.clone());
+    let mut dirs: Vec<String> = [path.parent(), target.parent()]
+        .into_iter()
+        .flatten()
+        .filter(|dir| dir.starts_with(home))
+        .map(|dir| dir.to_string_lossy().to_string())
+        .collect();
+    dirs.dedup();
+    dirs
+}
+
+/// Paths Claude Code itself must be able to write: its config and credentials
+fn claude_state(home: &Path) -> Vec<String> {
+    [".claude", ".claude.json"]
+        .iter()
+        .map(|p| home.join(p))
+        .filter(|p| p.exists())
+        .map(|p| p.to_string_lossy().to_string())
+        .collect()
+}
+
+/// Writable paths: the working directory, the repository's git directory (which a
+/// worktree keeps outside its own directory), Claude Code's state, and configured extras
+fn writable_paths(profile: &SandboxProfile, cwd: &str, home: &Path) -> Vec<String> {
+    let mut paths = claude_state(home);
+    if let Some(git_dir) = git::common_dir(Path::new(cwd)) {
+        paths.push(git_dir);
+    }
+    paths.push(cwd.to_string());
+    paths.extend(
+        profile
+            .writable
+            .iter()
+            .map(|p| expand_tilde(p).to_string_lossy().to_string()),
+    );
+    paths.dedup();
+    paths
+}
+
+/// Paths inside the writable ones that stay read-only: git's config and hooks, and
+/// ccx's repo config, which ccx and git later act on outside the sandbox
+fn protected_paths(cwd: &str, toplevel: Option<&str>, git_dir: Option<&str>) -> Vec<String> {
+    let mut paths: Vec<String> = Vec::new();
+    if let Some(git_dir) = git_dir {
+        paths.push(format!("{}/config", git_dir));
+        paths.push(format!("{}/hooks", git_dir));
+    }
+    for dir in toplevel.into_iter().chain([cwd]) {
+        paths.push(format!("{}/.ccx", dir));
+    }
+    paths.dedup();
+    paths
+}
+
+/// Make sure every protected path exists, since a missing one could be created inside
+/// the sandbox. Missing directories are created empty (git ignores empty directories).
+fn ensure_protected(paths: &[String]) -> Result<()> {
+    for path in paths {
+        let path = Path::new(path);
+        if !path.exists() {
+            std::fs::create_dir_all(path)
+                .with_context(|| format!("Failed to create {}", path.display()))?;
+        }
+    }
+    Ok(())
+}
+
+fn bwrap_args(
+    profile: &SandboxProfile,
+    cwd: &str,
+    home: &Path,
+    writable: &[String],
+    protected: &[String],
+) -> Vec<String> {
+    let mut args: Vec<String> = [
+        "bwrap",
+        "--die-with-parent",
+        "--ro-bind",
+        "/",
+        "/",
+        "--dev",
+        "/dev",
+        "--proc",
+        "/proc",
+        "--tmpfs",
+        "/tmp",
+    ]
+    .iter()
+    .map(|s| s.to_string())
+    .collect();
+
+    // Mask the home directory, then expose only what the session needs
+    args.extend(["--tmpfs".to_string(), home.to_string_lossy().to_string()]);
+    let readonly = profile
+        .readonly
+        .iter()
+        .map(|p| expand_tilde(p).to_string_lossy().to_string())
+        .chain(claude_install(home));
+    for path in readonly {
+        args.extend(["--ro-bind".to_string(), path.clone(), path]);
+    }
+    for path in writable {
+        args.extend(["--bind".to_string(), path.clone(), path.clone()]);
+    }
+    for path in protected {
+        args.extend(["--ro-bind".to_string(), path.clone(), path.clone()]);
+    }
+    if profile.network == Network::None {
+        args.push("--unshare-net".to_string());
+    }
+    args.extend(["--chdir".to_string(), cwd.to_string(), "--".to_string()]);
+    args
+}
+
+fn container_args(
+    profile: &SandboxProfile,
+    cwd: &str,
+    home: &Path,
+    writable: &[String],
+    protected: &[String],
+    env_names: &[String],
+) -> Result<Vec<String>> {
+    let image = profile
+        .image
+        .clone()
+        .context("Container sandboxes need an `image` with claude installed")?;
+    let runtime = profile
+        .runtime
+        .clone()
+        .unwrap_or_else(|| "podman".to_string());
+    let mut args = vec![
+        runtime.clone(),
+        "run".to_string(),
+        "--rm".to_string(),
+        "-it".to_string(),
+    ];
+    if runtime == "podman" {
+        args.push("--userns=keep-id".to_string());
+    }
+    for path in writable {
+        args.extend(["-v".to_string(), format!("{}:{}", path, path)]);
+    }
+    let readonly = profile
+        .readonly
+        .iter()
+        .map(|p| expand_tilde(p).to_string_lossy().to_string())
+        .chain(protected.iter().cloned());
+    for path in readonly {
+        args.extend(["-v".to_string(), format!("{}:{}:ro", path, path)]);
+    }
+    // Pass variables by name so their values stay out of the command line
+    for name in env_names {
+        args.extend(["-e".to_string(), name.clone()]);
+    }
+    args.extend([
+        "-e".to_string(),
+        format!("HOME={}", home.to_string_lossy()),
+        "-w".to_string(),
+        cwd.to_string(),
+        format!(
+            "--network={}",
+            match profile.network {
+                Network::Host => "host",
+                Network::None => "none",
+            }
+        ),
+        image,
+    ]);
+    Ok(args)
+}
+
+/// The command prefix that runs the agent in `cwd` under a sandbox profile, passing
+/// through the environment variables in `env_names`
+pub fn wrapper(profile: &SandboxProfile, cwd: &str, env_names: &[String]) -> Result<Vec<String>> {
+    let home = std::env::var_os("HOME").context("HOME not set")?;
+    let home = Path::new(&home);
+    let writable = writable_paths(profile, cwd, home);
+    let protected = protected_paths(
+        cwd,
+        git::toplevel(Path::new(cwd)).as_deref(),
+        git::common_dir(Path::new(cwd)).as_deref(),
+    );
+    let args = match profile.backend {
+        Backend::Bwrap => bwrap_args(profile, cwd, home, &writable, &protected),
+        Backend::Container => container_args(profile, cwd, home, &writable, &protected, env_names)?,
+    };
+    if find_on_path(&args[0]).is_none() {
+        anyhow::bail!("Sandbox backend '{}' is not installed", args[0]);
+    }
+    ensure_protected(&protected)?;
+    Ok(args)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bwrap_args() {
+        let profile = SandboxProfile {
+            network: Network::None,
+            readonly: vec!["/opt/sdk".to_string()],
+            ..Default::default()
+        };
+        let writable = vec!["/home/me/src/app".to_string()];
+        let protected = protected_paths("/home/me/src/app", None, None);
+        let args = bwrap_args(
+            &profile,
+            "/home/me/src/app",
+            Path::new("/home/me"),
+            &writable,
+            &protected,
+        );
+        let joined = args.join(" ");
+        assert!(joined.starts_with("bwrap --die-with-parent --ro-bind / /"));
+        assert!(joined.contains("--tmpfs /home/me --ro-bind /opt/sdk /opt/sdk"));
+        assert!(joined.contains(
+            "--bind /home/me/src/app /home/me/src/app \
+             --ro-bind /home/me/src/app/.ccx /home/me/src/app/.ccx"
+        ));
+        assert!(joined.contains("--unshare-net"));
+        assert!(joined.ends_with("--chdir /home/me/src/app --"));
+    }
+
+    #[test]
+    fn test_container_args() {
+        let profile = SandboxProfile {
+            backend: Backend::Container,
+            image: Some("claude:latest".to_string()),
+            ..Default::default()
+        };
+        let writable = vec!["/src/app".to_string()];
+        let args = container_args(
+            &profile,
+            "/src/app",
+            Path::new("/home/me"),
+            &writable,
+            &["/src/app/.ccx".to_string()],
+            &["TOKEN".to_string()],
+        )
+        .unwrap();
+        let joined = args.join(" ");
+        assert!(joined.starts_with(
+            "podman run --rm -it --userns=keep-id -v /src/app:/src/app \
+             -v /src/app/.ccx:/src/app/.ccx:ro"
+        ));
+        assert!(
+            joined.ends_with("-e TOKEN -e HOME=/home/me -w /src/app --network=host claude:latest")
+        );
+
+        let no_image = SandboxProfile {
+            backend: Backend::Container,
+            ..Default::default()
+        };
+        assert!(
+            container_args(
+                &no_image,
+                "/src/app",
+                Path::new("/home/me"),
+                &writable,
+                &[],
+                &[]
+            )
+            .is_err()
+        );
+    }
+
+    #[test]
+    fn test_protected_paths() {
+        assert_eq!(
+            protected_paths("/src/app/sub", Some("/src/app"), Some("/src/app/.git")),
+            [
+                "/src/app/.git/config",
+                "/src/app/.git/hooks",
+                "/src/app/.ccx",
+                "/src/app/sub/.ccx"
+            ]
+        );
+        assert_eq!(
+            protected_paths("/src/app", Some("/src/app"), None),
+            ["/src/app/.ccx"]
+        );
+    }
+
+    #[test]
+    fn test_parse_profile() {
+        let profile: SandboxProfile = toml::from_str(
+            r#"
+            backend = "container"
+            network = "none"
+            writable = ["~/.cargo"]
+            image = "ghcr.io/me/claude"
+            "#,
+        )
+        .unwrap();
+        assert_eq!(profile.backend, Backend::Container);
+        assert_eq!(profile.network, Network::None);
+        assert_eq!(profile.writable, vec!["~/.cargo"]);
+    }
+}
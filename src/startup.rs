@@ -0,0 +1,209 @@
+use anyhow::{Context, Result};
+use regex::Regex;
+use serde::Deserialize;
+
+/// A startup dialog ccx answers automatically: when `pattern` matches the pane
+/// content, send `keys` (tmux key names such as `Enter`, `Down` or `1`)
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct StartupRule {
+    /// Name shown in the session log; a configured rule replaces a built-in one with
+    /// the same name
+    pub name: String,
+    /// Regex matched against the last lines of the pane
+    pub pattern: String,
+    /// Keys sent when the pattern matches
+    #[serde(default)]
+    pub keys: Vec<String>,
+    /// How many times the rule may fire for one launch
+    #[serde(default = "default_max_times")]
+    pub max_times: u32,
+    /// Give up on the launch instead of sending keys, for dialogs that need a person
+    #[serde(default)]
+    pub fail: bool,
+}
+
+fn default_max_times() -> u32 {
+    1
+}
+
+/// Rules for the dialogs Claude Code shows before it starts working
+pub fn default_rules() -> Vec<StartupRule> {
+    let rule = |name: &str, pattern: &str, keys: &[&str], fail: bool| StartupRule {
+        name: name.to_string(),
+        pattern: pattern.to_string(),
+        keys: keys.iter().map(|k| k.to_string()).collect(),
+        max_times: 1,
+        fail,
+    };
+    vec![
+        rule(
+            "trust-folder",
+            r"(?i)do you trust the files in this folder|is this a project you created or one you trust",
+            &["Enter"],
+            false,
+        ),
+        rule(
+            "bypass-permissions",
+            r"(?i)bypass permissions mode[\s\S]*yes, i accept",
+            &["Down", "Enter"],
+            false,
+        ),
+        rule(
+            "theme",
+            r"(?i)choose the text style that looks best with your terminal",
+            &["Enter"],
+            false,
+        ),
+        rule(
+            "api-key",
+            r"(?i)do you want to use this api key\?",
+            &["Up", "Enter"],
+            false,
+        ),
+        rule(
+            "login",
+            r"(?i)select login method|invalid api key|please run /login",
+            &[],
+            true,
+        ),
+        rule(
+            "update-notice",
+            r"(?i)(update|new version|release notes)[\s\S]*press enter to continue",
+            &["Enter"],
+            false,
+        ),
+    ]
+}
+
+/// What the responder decided for the current pane content
+#[derive(Debug, PartialEq, Eq)]
+pub enum Response<'a> {
+    /// Send these keys
+    Send { rule: &'a str, keys: &'a [String] },
+    /// A rule marked `fail` matched
+    Fail { rule: &'a str },
+}
+
+/// Applies startup rules to a launching session, counting how often each fired
+#[derive(Clone)]
+pub struct Responder {
+    rules: Vec<(StartupRule, Regex)>,
+    fired: Vec<u32>,
+}
+
+impl Responder {
+    /// Compile the configured rules followed by the built-in ones they don't replace
+    pub fn new(configured: &[StartupRule]) -> Result<Responder> {
+        let mut rules = Vec::new();
+        let defaults = default_rules()
+            .into_iter()
+            .filter(|d| !configured.iter().any(|c| c.name == d.name));
+        for rule in configured.iter().cloned().chain(defaults) {
+            let regex = Regex::new(&rule.pattern)
+                .with_context(|| format!("Invalid pattern in startup rule '{}'", rule.name))?;
+            rules.push((rule, regex));
+        }
+        let fired = vec![0; rules.len()];
+        Ok(Responder { rules, fired })
+    }
+
+    /// The first rule matching `content` that has not used up its `max_times`
+    pub fn respond(&mut self, content: &str) -> Option<Response<'_>> {
+        let i = self
+            .rules
+            .iter()
+            .enumerate()
+            .position(|(i, (rule, regex))| {
+                self.fired[i] < rule.max_times && regex.is_match(content)
+            })?;
+        self.fired[i] += 1;
+        let rule = &self.rules[i].0;
+        Some(match rule.fail {
+            true => Response::Fail { rule: &rule.name },
+            false => Response::Send {
+                rule: &rule.name,
+                keys: &rule.keys,
+            },
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_default_rules_compile() {
+        Responder::new(&[]).unwrap();
+    }
+
+    #[test]
+    fn test_trust_rule() {
+        let mut responder = Responder::new(&[]).unwrap();
+        let dialog = "Do you trust the files in this folder?\n\n/tmp/repo\n\n❯ 1. Yes, proceed";
+        assert_eq!(
+            responder.respond(dialog),
+            Some(Response::Send {
+                rule: "trust-folder",
+                keys: &["Enter".to_string()],
+            })
+        );
+        // max_times is 1
+        assert_eq!(responder.respond(dialog), None);
+    }
+
+    #[test]
+    fn test_unrelated_output_does_not_match() {
+        let mut responder = Responder::new(&[]).unwrap();
+        assert_eq!(
+            responder.respond("I trust this change is correct; building the trusted list"),
+            None
+        );
+    }
+
+    #[test]
+    fn test_login_fails() {
+        let mut responder = Responder::new(&[]).unwrap();
+        assert_eq!(
+            responder.respond("Select login method:\n❯ 1. Claude account"),
+            Some(Response::Fail { rule: "login" })
+        );
+    }
+
+    #[test]
+    fn test_configured_rule_replaces_default() {
+        let custom = vec![StartupRule {
+            name: "theme".to_string(),
+            pattern: "(?i)choose the text style".to_string(),
+            keys: vec!["Down".to_string(), "Enter".to_string()],
+            max_times: 2,
+            fail: false,
+        }];
+        let mut responder = Responder::new(&custom).unwrap();
+        let keys = ["Down".to_string(), "Enter".to_string()];
+        let theme = "Choose the text style that looks best with your terminal";
+        for _ in 0..2 {
+            assert_eq!(
+                responder.respond(theme),
+                Some(Response::Send {
+                    rule: "theme",
+                    keys: &keys,
+                })
+            );
+        }
+        assert_eq!(responder.respond(theme), None);
+    }
+
+    #[test]
+    fn test_invalid_pattern() {
+        let rule = StartupRule {
+            name: "bad".to_string(),
+            pattern: "(".to_string(),
+            keys: Vec::new(),
+            max_times: 1,
+            fail: false,
+        };
+        assert!(Responder::new(&[rule]).is_err());
+    }
+}
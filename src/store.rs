@@ -0,0 +1,304 @@
+use crate::agent::AgentOptions;
+use crate::git::{GitChanges, GitSnapshot};
+use crate::headless::RunResult;
+use crate::permissions::PermissionPolicy;
+use crate::tmux::StartOutcome;
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::PathBuf;
+
+/// Metadata ccx keeps about a session, stored at ~/.ccx/sessions/<session_name>.json
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SessionRecord {
+    pub name: String,
+    /// Absolute working directory the session was started in
+    pub cwd: String,
+    /// tmux id (`%N`) of the pane the agent runs in
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pane: Option<String>,
+    /// tmux target of the pane this session was adopted from, e.g. `work:1.0`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub adopted_from: Option<String>,
+    pub prompt: String,
+    /// Unix timestamp of session creation
+    pub created_at: u64,
+    /// Human-friendly label, e.g. the task name in a batch
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub label: Option<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<String>,
+    /// Batch this session was launched by
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub batch: Option<String>,
+    /// Worktree ccx created for this session, if any
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub worktree: Option<String>,
+    /// itack issue this session works on
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub issue: Option<IssueLink>,
+    /// Git state of the working directory at start (None outside a repo)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub git_start: Option<GitSnapshot>,
+    /// Git changes recorded when the session completed
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub git_end: Option<GitChanges>,
+    /// Review requested for this session's work
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub review: Option<Review>,
+    /// Worker session this session is reviewing
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reviews: Option<String>,
+    /// Session whose agent started this one
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub parent: Option<String>,
+    /// Most child sessions this session may start
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_children: Option<u32>,
+    /// Each attempt to launch Claude Code for this session
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub attempts: Vec<StartAttempt>,
+    /// Permission rules the supervisor answers prompts with (auto permission mode)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub permissions: Option<PermissionPolicy>,
+    /// Command run to notify a person about this session, from the config at start
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub notify_command: Option<String>,
+    /// Names of the environment variables set for the agent (values are never stored)
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub env: Vec<String>,
+    /// Runs claude in print mode rather than interactively
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub headless: bool,
+    /// How a headless run ended
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub result: Option<RunResult>,
+    /// Claude Code options the session was started with
+    #[serde(skip_serializing_if = "AgentOptions::is_empty")]
+    pub agent: AgentOptions,
+    /// Sandbox profile the agent runs under
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sandbox: Option<String>,
+    /// Layout whose panes were opened next to the agent
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub layout: Option<String>,
+    /// Command run to verify the session's work once it completes
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub verify: Option<Verification>,
+    /// Last assistant message from the session's transcript, recorded at completion
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub final_message: Option<String>,
+    /// Unix timestamp of when completion was recorded
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub completed_at: Option<u64>,
+    /// Exit status of the agent process; 128 + N if it was killed by signal N
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub exit_code: Option<i32>,
+    /// Unix timestamp of when the agent process exited
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ended_at: Option<u64>,
+}
+
+/// itack issue a session was started for
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct IssueLink {
+    pub id: String,
+    /// Repository holding the `.itack` directory
+    pub repo: String,
+    /// Path of the issue file
+    pub file: String,
+    /// Run `itack done` when the session completes
+    pub mark_done: bool,
+    /// Append session notes to the issue file when the session completes
+    pub add_notes: bool,
+}
+
+/// A reviewer session launched when the session completes
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Review {
+    /// What the reviewer should check
+    pub prompt: String,
+    /// The reviewer session, once started
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub session: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub verdict: Option<Verdict>,
+    /// The reviewer's final message
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub notes: Option<String>,
+}
+
+/// Outcome of a review
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Verdict {
+    Approved,
+    ChangesRequested,
+    /// The reviewer finished without a recognizable verdict
+    Unclear,
+}
+
+impl std::fmt::Display for Verdict {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Verdict::Approved => write!(f, "approved"),
+            Verdict::ChangesRequested => write!(f, "changes requested"),
+            Verdict::Unclear => write!(f, "no verdict"),
+        }
+    }
+}
+
+/// One attempt to launch Claude Code for a session
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StartAttempt {
+    /// Unix timestamp of the launch
+    pub at: u64,
+    pub outcome: StartOutcome,
+}
+
+/// Verification command for a session and the results of running it
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Verification {
+    /// Shell command run in the session's directory
+    pub command: String,
+    /// How many times a failure is sent back into the session for another attempt
+    pub max_rounds: u32,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub runs: Vec<VerifyRun>,
+}
+
+impl Verification {
+    /// Whether the latest run passed, or None if it has not run yet
+    pub fn passed(&self) -> Option<bool> {
+        self.runs.last().map(|run| run.exit_code == Some(0))
+    }
+}
+
+/// One run of a verification command
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct VerifyRun {
+    /// Exit code, or None if the command was killed by a signal
+    pub exit_code: Option<i32>,
+    /// Unix timestamp of when the run finished
+    pub at: u64,
+}
+
+pub fn sessions_dir() -> Result<PathBuf> {
+    let home = std::env::var("HOME").context("HOME not set")?;
+    Ok(PathBuf::from(home).join(".ccx").join("sessions"))
+}
+
+fn record_path(session_name: &str) -> Result<PathBuf> {
+    Ok(sessions_dir()?.join(format!("{}.json", session_name)))
+}
+
+/// Current time as a unix timestamp
+pub fn now() -> u64 {
+    use std::time::{SystemTime, UNIX_EPOCH};
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
+/// Load the record for a session, if one exists
+pub fn load(session_name: &str) -> Result<Option<SessionRecord>> {
+    let path = record_path(session_name)?;
+    if !path.exists() {
+        return Ok(None);
+    }
+    let content =
+        fs::read_to_string(&path).with_context(|| format!("Failed to read {}", path.display()))?;
+    let record = serde_json::from_str(&content)
+        .with_context(|| format!("Failed to parse {}", path.display()))?;
+    Ok(Some(record))
+}
+
+/// Load every session record, skipping unreadable ones
+pub fn list() -> Result<Vec<SessionRecord>> {
+    let dir = sessions_dir()?;
+    if !dir.exists() {
+        return Ok(Vec::new());
+    }
+    let records = fs::read_dir(&dir)
+        .with_context(|| format!("Failed to read {}", dir.display()))?
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "json"))
+        .filter_map(|path| fs::read_to_string(path).ok())
+        .filter_map(|content| serde_json::from_str(&content).ok())
+        .collect();
+    Ok(records)
+}
+
+/// Write a session record, replacing any existing one
+pub fn save(record: &SessionRecord) -> Result<()> {
+    let dir = sessions_dir()?;
+    fs::create_dir_all(&dir).with_context(|| format!("Failed to create {}", dir.display()))?;
+    let path = record_path(&record.name)?;
+    let content = serde_json::to_string_pretty(record)?;
+    fs::write(&path, content).with_context(|| format!("Failed to write {}", path.display()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_record_defaults_missing_fields() {
+        let record: SessionRecord =
+            serde_json::from_str(r#"{"name": "ccx-0000abcd", "cwd": "/tmp"}"#).unwrap();
+        assert_eq!(record.name, "ccx-0000abcd");
+        assert_eq!(record.prompt, "");
+        assert!(record.git_start.is_none());
+        assert!(record.completed_at.is_none());
+    }
+
+    #[test]
+    fn test_record_roundtrip() {
+        let record = SessionRecord {
+            name: "ccx-0000abcd".to_string(),
+            cwd: "/tmp/repo".to_string(),
+            prompt: "fix the bug".to_string(),
+            created_at: 42,
+            git_start: Some(GitSnapshot {
+                toplevel: "/tmp/repo".to_string(),
+                head: Some("abc123".to_string()),
+                branch: None,
+                dirty: true,
+            }),
+            ..Default::default()
+        };
+        let json = serde_json::to_string(&record).unwrap();
+        let parsed: SessionRecord = serde_json::from_str(&json).unwrap();
+        assert_eq!(parsed.git_start, record.git_start);
+        assert_eq!(parsed.created_at, 42);
+        assert!(!json.contains("git_end"));
+    }
+
+    #[test]
+    fn test_start_attempts_serialize() {
+        let record = SessionRecord {
+            attempts: vec![
+                StartAttempt {
+                    at: 1,
+                    outcome: StartOutcome::TimedOut,
+                },
+                StartAttempt {
+                    at: 40,
+                    outcome: StartOutcome::Started,
+                },
+            ],
+            ..Default::default()
+        };
+        let json = serde_json::to_string(&record).unwrap();
+        assert!(json.contains(r#""outcome":"timed_out""#));
+        let parsed: SessionRecord = serde_json::from_str(&json).unwrap();
+        assert_eq!(parsed.attempts[1].outcome, StartOutcome::Started);
+    }
+}
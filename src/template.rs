@@ -0,0 +1,263 @@
+use crate::{config, git};
+use anyhow::{Context, Result};
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// File extension of template files
+const EXTENSION: &str = "md";
+
+/// Where a template was found
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Scope {
+    /// ~/.ccx/templates
+    Global,
+    /// <repo>/.ccx/templates
+    Repo,
+}
+
+impl std::fmt::Display for Scope {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Scope::Global => write!(f, "global"),
+            Scope::Repo => write!(f, "repo"),
+        }
+    }
+}
+
+/// A named prompt template file
+#[derive(Debug, Clone)]
+pub struct Template {
+    pub name: String,
+    pub path: PathBuf,
+    pub scope: Scope,
+}
+
+/// Directory holding templates for a scope
+pub fn templates_dir(scope: Scope, repo: Option<&Path>) -> Result<PathBuf> {
+    match (scope, repo) {
+        (Scope::Global, _) => Ok(config::ccx_home()?.join("templates")),
+        (Scope::Repo, Some(repo)) => Ok(repo.join(".ccx").join("templates")),
+        (Scope::Repo, None) => anyhow::bail!("Not inside a git repository"),
+    }
+}
+
+/// Path of the template file `name` in a scope (which may not exist yet)
+pub fn template_path(scope: Scope, repo: Option<&Path>, name: &str) -> Result<PathBuf> {
+    check_name(name)?;
+    Ok(templates_dir(scope, repo)?.join(format!("{}.{}", name, EXTENSION)))
+}
+
+/// A template name must stay inside the templates directory
+fn check_name(name: &str) -> Result<()> {
+    if name.is_empty() || name.contains(['/', '\\']) || name.contains("..") {
+        anyhow::bail!(
+            "Invalid template name '{}' (names can't be empty or contain '/', '\\' or '..')",
+            name
+        );
+    }
+    Ok(())
+}
+
+fn templates_in(scope: Scope, repo: Option<&Path>) -> Result<Vec<Template>> {
+    let dir = templates_dir(scope, repo)?;
+    if !dir.exists() {
+        return Ok(Vec::new());
+    }
+    let mut templates: Vec<Template> = fs::read_dir(&dir)
+        .with_context(|| format!("Failed to read {}", dir.display()))?
+        .filter_map(|e| e.ok())
+        .map(|e| e.path())
+        .filter(|p| p.extension().is_some_and(|ext| ext == EXTENSION))
+        .filter_map(|path| {
+            let name = path.file_stem()?.to_string_lossy().to_string();
+            Some(Template { name, path, scope })
+        })
+        .collect();
+    templates.sort_by(|a, b| a.name.cmp(&b.name));
+    Ok(templates)
+}
+
+/// All templates visible from `repo`. Repo templates shadow global ones with the same name.
+pub fn list(repo: Option<&Path>) -> Result<Vec<Template>> {
+    let mut templates = Vec::new();
+    if repo.is_some() {
+        templates = templates_in(Scope::Repo, repo)?;
+    }
+    for global in templates_in(Scope::Global, None)? {
+        if !templates.iter().any(|t| t.name == global.name) {
+            templates.push(global);
+        }
+    }
+    templates.sort_by(|a, b| a.name.cmp(&b.name));
+    Ok(templates)
+}
+
+/// Find a template by name, preferring the repo's own templates
+pub fn find(name: &str, repo: Option<&Path>) -> Result<Template> {
+    list(repo)?
+        .into_iter()
+        .find(|t| t.name == name)
+        .with_context(|| format!("Template '{}' not found", name))
+}
+
+/// Parse a `KEY=VALUE` command-line variable
+pub fn parse_var(s: &str) -> Result<(String, String), String> {
+    match s.split_once('=') {
+        Some((key, value)) if !key.is_empty() => Ok((key.to_string(), value.to_string())),
+        _ => Err(format!("expected KEY=VALUE, got '{}'", s)),
+    }
+}
+
+/// Render a named template for a session starting in `dir`.
+/// Built-in variables `cwd`, `repo` and `branch` are available, and `{{env.NAME}}`
+/// reads environment variables; `vars` override the built-ins.
+/// Fails if any placeholder is left without a value.
+pub fn render_named(name: &str, dir: &Path, vars: &[(String, String)]) -> Result<String> {
+    let snapshot = git::snapshot(dir);
+    let repo = snapshot.as_ref().map(|s| PathBuf::from(&s.toplevel));
+    let template = find(name, repo.as_deref())?;
+    let text = fs::read_to_string(&template.path)
+        .with_context(|| format!("Failed to read {}", template.path.display()))?;
+
+    let mut all_vars = HashMap::new();
+    all_vars.insert("cwd".to_string(), dir.to_string_lossy().to_string());
+    if let Some(snap) = &snapshot {
+        all_vars.insert("repo".to_string(), snap.toplevel.clone());
+        if let Some(branch) = &snap.branch {
+            all_vars.insert("branch".to_string(), branch.clone());
+        }
+    }
+    all_vars.extend(vars.iter().cloned());
+
+    let missing = missing_vars(&text, &all_vars);
+    if !missing.is_empty() {
+        anyhow::bail!(
+            "Template '{}' needs values for: {} (pass --var KEY=VALUE)",
+            name,
+            missing.join(", ")
+        );
+    }
+
+    Ok(render(&text, &all_vars).trim().to_string())
+}
+
+/// Look up a placeholder: `env.NAME` reads the environment, anything else reads `vars`
+fn lookup(key: &str, vars: &HashMap<String, String>) -> Option<String> {
+    match key.strip_prefix("env.") {
+        Some(name) => std::env::var(name).ok(),
+        None => vars.get(key).cloned(),
+    }
+}
+
+/// Placeholder names in `text` that have no value
+pub fn missing_vars(text: &str, vars: &HashMap<String, String>) -> Vec<String> {
+    let mut missing = Vec::new();
+    let mut rest = text;
+    while let Some(start) = rest.find("{{") {
+        let after = &rest[start + 2..];
+        let Some(end) = after.find("}}") else {
+            break;
+        };
+        let key = after[..end].trim().to_string();
+        if lookup(&key, vars).is_none() && !missing.contains(&key) {
+            missing.push(key);
+        }
+        rest = &after[end + 2..];
+    }
+    missing
+}
+
+/// Replace `{{name}}` placeholders with values from `vars` (or the environment for
+/// `{{env.NAME}}`). Placeholders without a value are left untouched.
+pub fn render(text: &str, vars: &HashMap<String, String>) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut rest = text;
+
+    while let Some(start) = rest.find("{{") {
+        out.push_str(&rest[..start]);
+        let after = &rest[start + 2..];
+        let Some(end) = after.find("}}") else {
+            out.push_str(&rest[start..]);
+            return out;
+        };
+        let key = after[..end].trim();
+        match lookup(key, vars) {
+            Some(value) => out.push_str(&value),
+            None => out.push_str(&rest[start..start + end + 4]),
+        }
+        rest = &after[end + 2..];
+    }
+
+    out.push_str(rest);
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn vars(pairs: &[(&str, &str)]) -> HashMap<String, String> {
+        pairs
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn test_render_substitutes() {
+        let v = vars(&[("id", "42"), ("title", "Fix it")]);
+        assert_eq!(render("Issue {{id}}: {{ title }}", &v), "Issue 42: Fix it");
+    }
+
+    #[test]
+    fn test_render_env() {
+        let v = vars(&[]);
+        let path = std::env::var("PATH").unwrap();
+        assert_eq!(render("{{env.PATH}}", &v), path);
+    }
+
+    #[test]
+    fn test_missing_vars() {
+        let v = vars(&[("issue", "42")]);
+        assert_eq!(
+            missing_vars(
+                "{{issue}} {{file}} {{ file }} {{env.CCX_NOT_SET_ANYWHERE}}",
+                &v
+            ),
+            vec!["file", "env.CCX_NOT_SET_ANYWHERE"]
+        );
+    }
+
+    #[test]
+    fn test_check_name() {
+        assert!(check_name("fix-bug").is_ok());
+        assert!(check_name("v1.2").is_ok());
+        assert!(check_name("").is_err());
+        assert!(check_name("../../.bashrc").is_err());
+        assert!(check_name("sub/name").is_err());
+        assert!(check_name("sub\\name").is_err());
+        assert!(check_name("..").is_err());
+    }
+
+    #[test]
+    fn test_parse_var() {
+        assert_eq!(
+            parse_var("issue=42"),
+            Ok(("issue".to_string(), "42".to_string()))
+        );
+        assert_eq!(
+            parse_var("msg=a=b"),
+            Ok(("msg".to_string(), "a=b".to_string()))
+        );
+        assert!(parse_var("novalue").is_err());
+        assert!(parse_var("=x").is_err());
+    }
+
+    #[test]
+    fn test_render_leaves_unknown_and_unclosed() {
+        let v = vars(&[("id", "42")]);
+        assert_eq!(render("{{missing}} {{id}}", &v), "{{missing}} 42");
+        assert_eq!(render("open {{id", &v), "open {{id");
+    }
+}
@@ -1,12 +1,80 @@
+use crate::config;
+use crate::startup::{Responder, Response};
+use crate::store;
 use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
 use std::fs;
 use std::io::Write;
+use std::path::{Path, PathBuf};
 use std::process::Command;
+use std::sync::OnceLock;
+use std::time::Duration;
 
 const SESSION_PREFIX: &str = "ccx-";
 
+/// Config written for a dedicated server the first time it is used
+const DEFAULT_SERVER_CONFIG: &str = "\
+# tmux config for ccx's own server (tmux -L <socket>), created by ccx; edit freely.
+# Changes apply once the server restarts (after every ccx session has ended).
+set -g history-limit 50000
+set -g status-left '[ccx] #S '
+set -g status-left-length 40
+set -g status-right '#{pane_title}'
+set -g status-right-length 80
+";
+
+/// `[tmux]` in ~/.ccx/config.toml
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TmuxSettings {
+    /// Run ccx sessions on a dedicated tmux server with this socket name
+    /// (`tmux -L <socket>`) instead of the user's default server
+    pub socket: Option<String>,
+    /// Config file for the dedicated server (default: ~/.ccx/tmux.conf, created if missing)
+    pub config: Option<String>,
+}
+
+/// Arguments selecting ccx's tmux server, read from the config once per process
+fn server_args() -> &'static [String] {
+    static ARGS: OnceLock<Vec<String>> = OnceLock::new();
+    ARGS.get_or_init(|| {
+        let settings = config::load_global()
+            .map(|config| config.tmux)
+            .unwrap_or_default();
+        let Some(socket) = settings.socket else {
+            return Vec::new();
+        };
+        let conf = match settings.config {
+            Some(path) => config::expand_tilde(&path),
+            None => {
+                let path = config::ccx_home()
+                    .unwrap_or_else(|_| PathBuf::from(".ccx"))
+                    .join("tmux.conf");
+                if !path.exists() {
+                    let _ = fs::create_dir_all(path.parent().unwrap_or(Path::new(".")));
+                    let _ = fs::write(&path, DEFAULT_SERVER_CONFIG);
+                }
+                path
+            }
+        };
+        vec![
+            "-L".to_string(),
+            socket,
+            "-f".to_string(),
+            conf.to_string_lossy().to_string(),
+        ]
+    })
+}
+
+/// A tmux command on ccx's server
+fn tmux_command() -> Command {
+    let mut command = Command::new("tmux");
+    command.args(server_args());
+    command
+}
+
 /// Write a log entry for a session to ~/.ccx/logs/<session_name>.log
-fn session_log(session_name: &str, message: &str) {
+pub fn session_log(session_name: &str, message: &str) {
     let Some(home) = std::env::var_os("HOME") else {
         return;
     };
@@ -41,7 +109,7 @@ pub fn generate_session_name() -> String {
 }
 
 /// Simple random ID generator using process ID and timestamp
-fn rand_id() -> u32 {
+pub fn rand_id() -> u32 {
     use std::time::{SystemTime, UNIX_EPOCH};
     let time = SystemTime::now()
         .duration_since(UNIX_EPOCH)
@@ -51,18 +119,157 @@ fn rand_id() -> u32 {
     time.wrapping_add(pid)
 }
 
-/// Create a new detached tmux session running claude with the given prompt.
-/// Uses `env -i` to start claude with a clean environment, preventing inherited
-/// variables (like CLAUDECODE) from causing nested-session detection failures.
-pub fn create_session(session_name: &str, prompt: &str, cwd: Option<&str>) -> Result<()> {
-    let escaped_prompt = prompt.replace('"', "\\\"");
-
-    // Unset the env var that triggers Claude Code's nested-session detection.
-    let claude_cmd = format!(
-        "exec env -u CLAUDECODE -u CLAUDE_CODE_ENTRYPOINT claude --dangerously-skip-permissions \"{escaped_prompt}\"",
-    );
+/// Set in each agent's environment to its session name, so sessions it starts with
+/// ccx are recorded as its children
+pub const SESSION_ENV: &str = "CCX_SESSION";
+
+/// The claude invocation run in a session's pane
+#[derive(Clone, Default)]
+pub struct AgentCommand {
+    /// Session the agent runs in, exported as [`SESSION_ENV`]
+    pub session: String,
+    pub prompt: String,
+    /// Run with `--dangerously-skip-permissions`
+    pub skip_permissions: bool,
+    /// Command the agent runs under, e.g. a sandbox, ending with its own `--`
+    pub wrapper: Vec<String>,
+    /// Extra claude arguments, placed after the prompt
+    pub args: Vec<String>,
+    /// Environment variables for the agent. Values may be secrets, so they are passed
+    /// through a private file rather than the command line.
+    pub env: Vec<(String, String)>,
+}
+
+/// Longest prompt passed on the tmux command line. tmux rejects commands much over
+/// 16KB, so longer prompts go through the session's private env file.
+const MAX_INLINE_PROMPT: usize = 8 * 1024;
 
-    let mut args = vec!["new-session", "-d", "-s", session_name];
+/// Shell variable a long prompt is read from, set (not exported) by the env file
+const PROMPT_VAR: &str = "ccx_prompt";
+
+/// Write a session's environment (and a long prompt) to a file only the user can
+/// read, sourced and deleted by the pane's shell before claude starts
+fn write_env_file(session_name: &str, agent: &AgentCommand) -> Result<PathBuf> {
+    use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
+
+    let path = env_file_path(session_name)?;
+    let dir = path.parent().unwrap_or(Path::new("."));
+    fs::create_dir_all(dir).with_context(|| format!("Failed to create {}", dir.display()))?;
+    fs::set_permissions(dir, fs::Permissions::from_mode(0o700))
+        .with_context(|| format!("Failed to restrict {}", dir.display()))?;
+    let mut f = fs::OpenOptions::new()
+        .create(true)
+        .write(true)
+        .truncate(true)
+        .mode(0o600)
+        .open(&path)
+        .with_context(|| format!("Failed to write {}", path.display()))?;
+    f.write_all(agent.env_script().as_bytes())?;
+    Ok(path)
+}
+
+/// ~/.ccx/env/<session_name>.env
+fn env_file_path(session_name: &str) -> Result<PathBuf> {
+    let home = std::env::var_os("HOME").context("HOME not set")?;
+    Ok(Path::new(&home)
+        .join(".ccx")
+        .join("env")
+        .join(format!("{}.env", session_name)))
+}
+
+/// Remove a session's env file if the pane did not get to it, e.g. because the pane
+/// was never created or was killed before its shell ran
+pub fn remove_env_file(session_name: &str) {
+    if let Ok(path) = env_file_path(session_name) {
+        let _ = fs::remove_file(path);
+    }
+}
+
+impl AgentCommand {
+    /// Whether the prompt is too long for the command line
+    fn prompt_in_file(&self) -> bool {
+        self.prompt.len() > MAX_INLINE_PROMPT
+    }
+
+    /// Whether the pane command needs an env file
+    fn needs_env_file(&self) -> bool {
+        !self.env.is_empty() || self.prompt_in_file()
+    }
+
+    /// Contents of the env file
+    fn env_script(&self) -> String {
+        let mut script: String = self
+            .env
+            .iter()
+            .map(|(key, value)| format!("export {}={}\n", key, shell_quote(value)))
+            .collect();
+        if self.prompt_in_file() {
+            script.push_str(&format!("{}={}\n", PROMPT_VAR, shell_quote(&self.prompt)));
+        }
+        script
+    }
+
+    /// Shell command line for the pane, sourcing and removing `env_file` first.
+    /// Unsets the env vars that trigger Claude Code's nested-session detection.
+    pub fn shell_command(&self, env_file: Option<&Path>) -> String {
+        let prompt = match self.prompt_in_file() && env_file.is_some() {
+            true => format!("\"${}\"", PROMPT_VAR),
+            false => shell_quote(&self.prompt),
+        };
+        let permissions_flag = if self.skip_permissions {
+            " --dangerously-skip-permissions"
+        } else {
+            ""
+        };
+        let wrapper: String = self
+            .wrapper
+            .iter()
+            .map(|arg| format!("{} ", shell_quote(arg)))
+            .collect();
+        let args: String = self
+            .args
+            .iter()
+            .map(|arg| format!(" {}", shell_quote(arg)))
+            .collect();
+        let source_env = match env_file {
+            Some(path) => {
+                let path = shell_quote(&path.to_string_lossy());
+                format!(". {path}; rm -f {path}; ")
+            }
+            None => String::new(),
+        };
+        format!(
+            "{source_env}exec {wrapper}env -u CLAUDECODE -u CLAUDE_CODE_ENTRYPOINT {SESSION_ENV}={} claude{permissions_flag} {prompt}{args}",
+            shell_quote(&self.session)
+        )
+    }
+}
+
+/// Create a new detached tmux session running claude with the given prompt.
+/// Uses `env -u` to start claude without inherited variables (like CLAUDECODE) that
+/// would cause nested-session detection failures. The pane is kept when claude exits
+/// so its exit status can be read, and `on_exit` (a shell command) is run then.
+/// Returns the id (`%N`) of the agent's pane.
+pub fn create_session(
+    session_name: &str,
+    agent: &AgentCommand,
+    cwd: Option<&str>,
+    on_exit: &str,
+) -> Result<String> {
+    let env_file = match agent.needs_env_file() {
+        true => Some(write_env_file(session_name, agent)?),
+        false => None,
+    };
+    let claude_cmd = agent.shell_command(env_file.as_deref());
+    let mut args = vec![
+        "new-session",
+        "-d",
+        "-P",
+        "-F",
+        "#{pane_id}",
+        "-s",
+        session_name,
+    ];
 
     if let Some(dir) = cwd {
         args.push("-c");
@@ -70,91 +277,340 @@ pub fn create_session(session_name: &str, prompt: &str, cwd: Option<&str>) -> Re
     }
 
     args.push(&claude_cmd);
+    // Set in the same invocation, so even an immediate exit leaves the pane behind
+    args.extend([
+        ";",
+        "set-option",
+        "-w",
+        "-t",
+        session_name,
+        "remain-on-exit",
+        "on",
+    ]);
 
     session_log(
         session_name,
         &format!(
             "Created session (cwd: {}, prompt: {})",
             cwd.unwrap_or("."),
-            prompt
+            agent.prompt
         ),
     );
 
-    let status = Command::new("tmux")
+    let output = tmux_command()
         .args(&args)
-        .status()
-        .context("Failed to execute tmux")?;
+        .stderr(std::process::Stdio::inherit())
+        .output();
+    let output = match output {
+        Ok(output) if output.status.success() => output,
+        failed => {
+            remove_env_file(session_name);
+            session_log(session_name, "Failed to create tmux session");
+            failed.context("Failed to execute tmux")?;
+            anyhow::bail!("Failed to create tmux session");
+        }
+    };
+    let pane = String::from_utf8_lossy(&output.stdout).trim().to_string();
+    session_log(session_name, &format!("Agent pane: {}", pane));
+    watch_pane(session_name, &pane, on_exit);
 
-    if !status.success() {
-        session_log(session_name, "Failed to create tmux session");
-        anyhow::bail!("Failed to create tmux session");
-    }
+    Ok(pane)
+}
 
+/// Let the agent title its pane, and run `on_exit` when the agent pane dies
+fn watch_pane(session_name: &str, pane: &str, on_exit: &str) {
     // Enable title updates so Claude Code can set pane title with status icon
-    let _ = Command::new("tmux")
+    let _ = tmux_command()
         .args(["set-option", "-t", session_name, "allow-rename", "on"])
+        .stderr(std::process::Stdio::null())
         .status();
 
-    // Auto-accept the trusted folder prompt if Claude Code shows one
-    auto_trust_if_needed(session_name);
+    let _ = tmux_command()
+        .args([
+            "set-hook",
+            "-w",
+            "-t",
+            pane,
+            "pane-died",
+            &format!("run-shell -b {}", shell_quote(on_exit)),
+        ])
+        .stderr(std::process::Stdio::null())
+        .status();
+}
 
-    Ok(())
+/// A pane found by [`list_panes`] or [`find_pane`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Pane {
+    /// Pane id, e.g. `%3`
+    pub id: String,
+    pub session: String,
+    /// `session:window.pane`
+    pub target: String,
+    /// Process the pane was started with, usually a shell
+    pub pid: u32,
+    pub cwd: String,
+    pub title: String,
+    /// Whether it is on the user's default tmux server rather than ccx's dedicated one
+    /// (`[tmux] socket`), where ccx cannot manage it
+    pub other_server: bool,
+}
+
+const PANE_FORMAT: &str = "#{pane_id}\t#{session_name}\t#{window_index}\t#{pane_index}\t\
+    #{pane_pid}\t#{pane_current_path}\t#{pane_title}";
+
+/// Parse one line of `PANE_FORMAT` output
+fn parse_pane(line: &str) -> Option<Pane> {
+    let fields: Vec<&str> = line.splitn(7, '\t').collect();
+    let [id, session, window, index, pid, cwd, title] = fields[..] else {
+        return None;
+    };
+    Some(Pane {
+        id: id.to_string(),
+        session: session.to_string(),
+        target: format!("{}:{}.{}", session, window, index),
+        pid: pid.parse().ok()?,
+        cwd: cwd.to_string(),
+        title: title.to_string(),
+        other_server: false,
+    })
 }
 
-/// Poll a newly created session and auto-accept the trusted folder prompt if present.
-/// Claude Code may ask to trust the working directory before starting work.
-/// Marks the session as failed if it dies before starting.
-fn auto_trust_if_needed(session_name: &str) {
+/// The tmux servers to search for panes: ccx's, then the user's default server if ccx
+/// runs a dedicated one. Plain `tmux` reaches the server the user works in.
+fn pane_servers() -> Vec<(Command, bool)> {
+    let mut servers = vec![(tmux_command(), false)];
+    if !server_args().is_empty() {
+        servers.push((Command::new("tmux"), true));
+    }
+    servers
+}
+
+/// Every pane on ccx's tmux server and the user's default one
+pub fn list_panes() -> Result<Vec<Pane>> {
+    let mut panes = Vec::new();
+    for (mut command, other_server) in pane_servers() {
+        let output = command
+            .args(["list-panes", "-a", "-F", PANE_FORMAT])
+            .stderr(std::process::Stdio::null())
+            .output()
+            .context("Failed to execute tmux")?;
+        if !output.status.success() {
+            // No server running means no panes
+            continue;
+        }
+        panes.extend(
+            String::from_utf8_lossy(&output.stdout)
+                .lines()
+                .filter_map(parse_pane)
+                .map(|pane| Pane {
+                    other_server,
+                    ..pane
+                }),
+        );
+    }
+    Ok(panes)
+}
+
+/// Look up the pane a tmux target (`work`, `work:1.0`, `%3`, ...) refers to, on ccx's
+/// tmux server or else the user's default one
+pub fn find_pane(target: &str) -> Result<Pane> {
+    for (mut command, other_server) in pane_servers() {
+        let output = command
+            .args(["display-message", "-p", "-t", target, PANE_FORMAT])
+            .stderr(std::process::Stdio::null())
+            .output()
+            .context("Failed to execute tmux")?;
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        if let Some(pane) = stdout.lines().next().and_then(parse_pane)
+            && output.status.success()
+        {
+            return Ok(Pane {
+                other_server,
+                ..pane
+            });
+        }
+    }
+    anyhow::bail!("No tmux pane matches '{}'", target)
+}
+
+/// tmux target for a session as a whole: an adopted session is the pane it was
+/// adopted as (the user's tmux session around it is not ccx's), anything else its
+/// tmux session
+fn session_target(session_name: &str) -> String {
+    store::load(session_name)
+        .ok()
+        .flatten()
+        .filter(|record| record.adopted_from.is_some())
+        .and_then(|record| record.pane)
+        .unwrap_or_else(|| session_name.to_string())
+}
+
+/// tmux target for a session's agent pane: the pane id recorded when it was created,
+/// or the session itself (meaning its active pane) if none was recorded
+fn agent_pane(session_name: &str) -> String {
+    store::load(session_name)
+        .ok()
+        .flatten()
+        .and_then(|record| record.pane)
+        .unwrap_or_else(|| session_name.to_string())
+}
+
+/// Whether a pane id still names a pane. (`display-message -t` on a missing pane
+/// succeeds with empty output, so the panes are listed instead.)
+fn pane_exists(pane: &str) -> bool {
+    tmux_command()
+        .args(["list-panes", "-a", "-F", "#{pane_id}"])
+        .stderr(std::process::Stdio::null())
+        .output()
+        .is_ok_and(|output| {
+            String::from_utf8_lossy(&output.stdout)
+                .lines()
+                .any(|id| id == pane)
+        })
+}
+
+/// Error for a failed command on a session's agent pane, naming a pane that has gone
+fn pane_error(session_name: &str, target: &str, action: &str) -> anyhow::Error {
+    if target.starts_with('%') && !pane_exists(target) {
+        return anyhow::anyhow!(
+            "The agent pane ({}) of session {} no longer exists; it was closed or killed",
+            target,
+            session_name
+        );
+    }
+    anyhow::anyhow!("Failed to {} for session {}", action, session_name)
+}
+
+/// Exit status of a session's agent once it has exited (its pane is kept by
+/// `remain-on-exit`), or None while it is running. 128 + N if killed by signal N.
+pub fn pane_exit_status(session_name: &str) -> Option<i32> {
+    let output = tmux_command()
+        .args([
+            "display-message",
+            "-t",
+            &agent_pane(session_name),
+            "-p",
+            "#{pane_dead}:#{pane_dead_status}:#{pane_dead_signal}",
+        ])
+        .stderr(std::process::Stdio::null())
+        .output()
+        .ok()?;
+    parse_pane_exit(&String::from_utf8_lossy(&output.stdout))
+}
+
+fn parse_pane_exit(output: &str) -> Option<i32> {
+    let mut fields = output.trim().split(':');
+    if fields.next()? != "1" {
+        return None;
+    }
+    let status = fields.next().and_then(|s| s.parse::<i32>().ok());
+    let signal = fields.next().and_then(|s| s.parse::<i32>().ok());
+    Some(status.or(signal.map(|signal| 128 + signal)).unwrap_or(1))
+}
+
+/// How a launch attempt ended
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum StartOutcome {
+    /// Claude Code set a working or done title
+    Started,
+    /// The session exited before Claude Code started
+    Exited,
+    /// Claude Code did not start within the startup timeout
+    TimedOut,
+    /// A startup rule found a dialog that needs a person, e.g. logging in
+    Blocked,
+}
+
+impl std::fmt::Display for StartOutcome {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            StartOutcome::Started => write!(f, "started"),
+            StartOutcome::Exited => write!(f, "exited before starting"),
+            StartOutcome::TimedOut => write!(f, "timed out"),
+            StartOutcome::Blocked => write!(f, "blocked by a startup dialog"),
+        }
+    }
+}
+
+/// Poll a newly created session until Claude Code starts working, answering startup
+/// dialogs (folder trust, theme, ...) with the responder's rules
+pub fn wait_for_start(
+    session_name: &str,
+    timeout: Duration,
+    responder: &mut Responder,
+) -> StartOutcome {
     use std::thread;
-    use std::time::Duration;
+    use std::time::Instant;
 
-    for _ in 0..10 {
+    let deadline = Instant::now() + timeout;
+    while Instant::now() < deadline {
         thread::sleep(Duration::from_millis(300));
 
-        if !session_exists(session_name) {
+        if !session_exists(session_name) || pane_exit_status(session_name).is_some() {
             session_log(session_name, "Session exited before starting");
-            eprintln!("Warning: session exited before starting");
-            return;
+            return StartOutcome::Exited;
         }
 
         // If claude is already working, no trust prompt to handle
         if let Ok(title) = get_pane_title(session_name) {
             let status = parse_status_from_title(&title);
             if matches!(status, SessionStatus::InProgress | SessionStatus::Done) {
-                return;
+                return StartOutcome::Started;
             }
         }
 
-        // Check pane content for the trust prompt
-        if let Ok(content) = capture_pane(session_name, 20) {
-            let lower = content.to_lowercase();
-            if lower.contains("trust") {
-                session_log(session_name, "Detected trust prompt, auto-accepting");
-                let _ = Command::new("tmux")
-                    .args(["send-keys", "-t", session_name, "Enter"])
+        // Check pane content for startup dialogs
+        let Ok(content) = capture_pane(session_name, 20) else {
+            continue;
+        };
+        match responder.respond(&content) {
+            Some(Response::Send { rule, keys }) => {
+                session_log(
+                    session_name,
+                    &format!(
+                        "Startup rule '{}' matched, sending {}",
+                        rule,
+                        keys.join(" ")
+                    ),
+                );
+                let _ = tmux_command()
+                    .args(["send-keys", "-t", &agent_pane(session_name)])
+                    .args(keys)
                     .status();
-                return;
             }
+            Some(Response::Fail { rule }) => {
+                session_log(
+                    session_name,
+                    &format!(
+                        "Startup rule '{}' matched, needs attention:\n{}",
+                        rule, content
+                    ),
+                );
+                return StartOutcome::Blocked;
+            }
+            None => {}
         }
     }
 
-    // Exhausted all polls without seeing claude start — mark as failed
-    if session_exists(session_name) {
-        session_log(session_name, "Timed out waiting for claude to start");
-        if let Ok(content) = capture_pane(session_name, 20) {
-            session_log(
-                session_name,
-                &format!("Pane content at timeout:\n{}", content),
-            );
-        }
-        set_pane_title(session_name, "❌ Failed to start");
-        eprintln!("Warning: session did not start within expected time");
+    session_log(session_name, "Timed out waiting for claude to start");
+    if let Ok(content) = capture_pane(session_name, 20) {
+        session_log(
+            session_name,
+            &format!("Pane content at timeout:\n{}", content),
+        );
     }
+    StartOutcome::TimedOut
+}
+
+/// Mark a session that never started as failed
+pub fn mark_failed(session_name: &str) {
+    set_pane_title(session_name, "❌ Failed to start");
 }
 
 /// List all ccx sessions
 pub fn list_sessions() -> Result<Vec<Session>> {
-    let output = Command::new("tmux")
+    let output = tmux_command()
         .args(["list-sessions", "-F", "#{session_name}:#{session_attached}"])
         .output()
         .context("Failed to execute tmux")?;
@@ -165,7 +621,7 @@ pub fn list_sessions() -> Result<Vec<Session>> {
     }
 
     let stdout = String::from_utf8_lossy(&output.stdout);
-    let sessions: Vec<Session> = stdout
+    let mut sessions: Vec<Session> = stdout
         .lines()
         .filter(|line| line.starts_with(SESSION_PREFIX))
         .filter_map(|line| {
@@ -180,31 +636,69 @@ pub fn list_sessions() -> Result<Vec<Session>> {
             }
         })
         .collect();
+    sessions.extend(adopted_sessions()?);
 
     Ok(sessions)
 }
 
+/// Adopted sessions whose pane is still open. They keep the user's tmux session, so
+/// are found through their records rather than by name.
+fn adopted_sessions() -> Result<Vec<Session>> {
+    let adopted: Vec<store::SessionRecord> = store::list()?
+        .into_iter()
+        .filter(|record| record.adopted_from.is_some() && record.ended_at.is_none())
+        .collect();
+    if adopted.is_empty() {
+        return Ok(Vec::new());
+    }
+    let output = tmux_command()
+        .args(["list-panes", "-a", "-F", "#{pane_id}:#{session_attached}"])
+        .stderr(std::process::Stdio::null())
+        .output()
+        .context("Failed to execute tmux")?;
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let panes: Vec<(&str, &str)> = stdout
+        .lines()
+        .filter_map(|line| line.split_once(':'))
+        .collect();
+    Ok(adopted
+        .into_iter()
+        .filter_map(|record| {
+            let pane = record.pane?;
+            let (_, attached) = panes.iter().find(|(id, _)| *id == pane)?;
+            Some(Session {
+                name: record.name,
+                attached: *attached != "0",
+            })
+        })
+        .collect())
+}
+
 /// Get the pane title for a session (contains Claude Code status icon)
 pub fn get_pane_title(session_name: &str) -> Result<String> {
-    let output = Command::new("tmux")
-        .args(["display-message", "-t", session_name, "-p", "#{pane_title}"])
+    let target = agent_pane(session_name);
+    let output = tmux_command()
+        .args(["display-message", "-t", &target, "-p", "#{pane_title}"])
         .output()
         .context("Failed to get pane title")?;
 
-    if !output.status.success() {
-        anyhow::bail!("Failed to get pane title for session {}", session_name);
+    let title = String::from_utf8_lossy(&output.stdout).trim().to_string();
+    // A missing pane also reads as an empty title
+    if !output.status.success() || (title.is_empty() && target.starts_with('%')) {
+        return Err(pane_error(session_name, &target, "get pane title"));
     }
 
-    Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+    Ok(title)
 }
 
 /// Get the current working directory for a session's pane
 pub fn get_pane_cwd(session_name: &str) -> Result<String> {
-    let output = Command::new("tmux")
+    let target = agent_pane(session_name);
+    let output = tmux_command()
         .args([
             "display-message",
             "-t",
-            session_name,
+            &target,
             "-p",
             "#{pane_current_path}",
         ])
@@ -212,7 +706,7 @@ pub fn get_pane_cwd(session_name: &str) -> Result<String> {
         .context("Failed to get pane cwd")?;
 
     if !output.status.success() {
-        anyhow::bail!("Failed to get pane cwd for session {}", session_name);
+        return Err(pane_error(session_name, &target, "get pane cwd"));
     }
 
     Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
@@ -220,11 +714,12 @@ pub fn get_pane_cwd(session_name: &str) -> Result<String> {
 
 /// Capture recent content from a session's pane
 pub fn capture_pane(session_name: &str, lines: i32) -> Result<String> {
-    let output = Command::new("tmux")
+    let target = agent_pane(session_name);
+    let output = tmux_command()
         .args([
             "capture-pane",
             "-t",
-            session_name,
+            &target,
             "-p",
             "-S",
             &format!("-{}", lines),
@@ -233,15 +728,35 @@ pub fn capture_pane(session_name: &str, lines: i32) -> Result<String> {
         .context("Failed to capture pane")?;
 
     if !output.status.success() {
-        anyhow::bail!("Failed to capture pane for session {}", session_name);
+        return Err(pane_error(session_name, &target, "capture pane"));
     }
 
     Ok(String::from_utf8_lossy(&output.stdout).to_string())
 }
 
+/// Whether a session's recorded agent pane has been closed, leaving the rest of the
+/// session (e.g. its layout panes) behind
+pub fn agent_gone(session_name: &str) -> bool {
+    let target = agent_pane(session_name);
+    target.starts_with('%') && !pane_exists(&target)
+}
+
+/// Close a session's agent pane. Other panes in its tmux session, like layout panes,
+/// stay open; the tmux session ends with its last pane.
+pub fn remove_agent_pane(session_name: &str) -> Result<()> {
+    let target = agent_pane(session_name);
+    if !target.starts_with('%') {
+        return kill_session(session_name);
+    }
+    if !pane_exists(&target) {
+        return Ok(());
+    }
+    run(&["kill-pane", "-t", &target])
+}
+
 /// Kill a tmux session by name
 pub fn kill_session(session_name: &str) -> Result<()> {
-    let status = Command::new("tmux")
+    let status = tmux_command()
         .args(["kill-session", "-t", session_name])
         .status()
         .context("Failed to execute tmux")?;
@@ -253,52 +768,216 @@ pub fn kill_session(session_name: &str) -> Result<()> {
     Ok(())
 }
 
+/// Run a tmux command, failing with tmux's error message
+pub fn run<S: AsRef<std::ffi::OsStr>>(args: &[S]) -> Result<()> {
+    let output = tmux_command()
+        .args(args)
+        .output()
+        .context("Failed to execute tmux")?;
+    if !output.status.success() {
+        anyhow::bail!(
+            "tmux {} failed: {}",
+            args.first()
+                .map(|arg| arg.as_ref().to_string_lossy())
+                .unwrap_or_default(),
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    Ok(())
+}
+
 /// Check if a session exists
 pub fn session_exists(session_name: &str) -> bool {
-    Command::new("tmux")
-        .args(["has-session", "-t", session_name])
+    tmux_command()
+        .args(["has-session", "-t", &session_target(session_name)])
+        .stderr(std::process::Stdio::null())
         .status()
         .map(|s| s.success())
         .unwrap_or(false)
 }
 
-/// Attach to an existing tmux session (replaces current process)
-pub fn attach_session(session_name: &str) -> Result<()> {
+/// Where `ccx attach` shows a session
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum AttachMode {
+    /// Take over this terminal, or switch the tmux client it belongs to
+    #[default]
+    Terminal,
+    /// In a popup over the current tmux window
+    Popup,
+    /// In a new pane split from the current tmux pane
+    Split,
+}
+
+/// Socket path of the tmux server this process runs inside, if any
+fn enclosing_server() -> Option<String> {
+    let tmux = std::env::var("TMUX").ok()?;
+    let socket = tmux.split(',').next()?;
+    (!socket.is_empty()).then(|| socket.to_string())
+}
+
+/// Socket path of ccx's tmux server
+fn server_socket() -> Option<String> {
+    let output = tmux_command()
+        .args(["display-message", "-p", "#{socket_path}"])
+        .stderr(std::process::Stdio::null())
+        .output()
+        .ok()?;
+    let socket = String::from_utf8_lossy(&output.stdout).trim().to_string();
+    (output.status.success() && !socket.is_empty()).then_some(socket)
+}
+
+/// Shell command running a tmux client attached to a session on the server at
+/// `socket`. `TMUX` is unset, as tmux refuses to start a client inside another one
+/// otherwise.
+fn attach_command(socket: &str, target: &str, read_only: bool) -> String {
+    let mut words = vec!["env", "-u", "TMUX", "tmux", "-S", socket, "attach-session"];
+    if read_only {
+        words.push("-r");
+    }
+    words.extend(["-t", target]);
+    words
+        .into_iter()
+        .map(shell_quote)
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Attach to an existing tmux session. Outside tmux this replaces the current process
+/// with a tmux client; inside tmux the current client switches to the session, or the
+/// session opens in a popup or split. A read-only attach from inside tmux opens a
+/// popup, since switching would leave the current client writable.
+pub fn attach_session(session_name: &str, mode: AttachMode, read_only: bool) -> Result<()> {
     use std::os::unix::process::CommandExt;
 
-    let err = Command::new("tmux")
-        .args(["attach-session", "-t", session_name])
-        .exec();
+    let enclosing = enclosing_server();
+    let mode = match mode {
+        AttachMode::Terminal if read_only && enclosing.is_some() => AttachMode::Popup,
+        mode => mode,
+    };
+    if mode != AttachMode::Terminal && enclosing.is_none() {
+        anyhow::bail!("Attaching in a popup or split needs to be run inside tmux");
+    }
+
+    // Popups and splits belong to the enclosing server, which need not be ccx's
+    let client = |args: &[&str]| -> Result<()> {
+        let status = Command::new("tmux")
+            .args(args)
+            .status()
+            .context("Failed to execute tmux")?;
+        if !status.success() {
+            anyhow::bail!("tmux {} failed", args[0]);
+        }
+        Ok(())
+    };
+    let socket = server_socket().context("Failed to find ccx's tmux server")?;
+    let target = session_target(session_name);
+    let command = attach_command(&socket, &target, read_only);
+    match mode {
+        AttachMode::Popup => client(&[
+            "display-popup",
+            "-E",
+            "-w",
+            "90%",
+            "-h",
+            "90%",
+            "-T",
+            &format!(" {} ", session_name),
+            &command,
+        ]),
+        AttachMode::Split => {
+            let pane = std::env::var("TMUX_PANE").unwrap_or_default();
+            client(&["split-window", "-t", &pane, &command])
+        }
+        AttachMode::Terminal if enclosing.as_ref() == Some(&socket) => {
+            run(&["switch-client", "-t", &target])
+        }
+        AttachMode::Terminal => {
+            // Inside a different tmux server this nests a client, which is all that
+            // can reach a session on ccx's dedicated server
+            let mut attach = tmux_command();
+            attach
+                .env_remove("TMUX")
+                .args(["attach-session", "-t", &target]);
+            if read_only {
+                attach.arg("-r");
+            }
+            let err = attach.exec();
 
-    // exec() only returns if it fails
-    Err(anyhow::anyhow!("Failed to exec tmux: {}", err))
+            // exec() only returns if it fails
+            Err(anyhow::anyhow!("Failed to exec tmux: {}", err))
+        }
+    }
 }
 
-/// Send keys to a tmux session
+/// Send keys to a session's agent pane
 pub fn send_keys(session_name: &str, text: &str) -> Result<()> {
+    let target = agent_pane(session_name);
     // Send text literally (prevents tmux from interpreting special sequences)
-    let status = Command::new("tmux")
-        .args(["send-keys", "-t", session_name, "-l", text])
+    let status = tmux_command()
+        .args(["send-keys", "-t", &target, "-l", text])
         .status()
         .context("Failed to execute tmux")?;
 
     if !status.success() {
-        anyhow::bail!("Failed to send keys to session {}", session_name);
+        return Err(pane_error(session_name, &target, "send keys"));
     }
 
     // Send Enter key separately
-    let status = Command::new("tmux")
-        .args(["send-keys", "-t", session_name, "Enter"])
+    let status = tmux_command()
+        .args(["send-keys", "-t", &target, "Enter"])
         .status()
         .context("Failed to execute tmux")?;
 
     if !status.success() {
-        anyhow::bail!("Failed to send Enter to session {}", session_name);
+        return Err(pane_error(session_name, &target, "send Enter"));
     }
 
     Ok(())
 }
 
+/// Send a single key (e.g. `Enter`, `Escape`) to a session's agent pane
+pub fn send_key(session_name: &str, key: &str) -> Result<()> {
+    let target = agent_pane(session_name);
+    let status = tmux_command()
+        .args(["send-keys", "-t", &target, key])
+        .status()
+        .context("Failed to execute tmux")?;
+
+    if !status.success() {
+        return Err(pane_error(session_name, &target, &format!("send {}", key)));
+    }
+
+    Ok(())
+}
+
+/// Show a message on every attached tmux client
+pub fn display_message(message: &str) {
+    let _ = tmux_command()
+        .args(["display-message", message])
+        .stderr(std::process::Stdio::null())
+        .status();
+}
+
+/// Run a shell command in the background from the tmux server, detached from this
+/// process and its terminal
+pub fn run_background(command: &str) -> Result<()> {
+    let status = tmux_command()
+        .args(["run-shell", "-b", command])
+        .status()
+        .context("Failed to execute tmux")?;
+
+    if !status.success() {
+        anyhow::bail!("Failed to run background command");
+    }
+
+    Ok(())
+}
+
+/// Quote a string for safe use as a single POSIX shell word
+pub fn shell_quote(s: &str) -> String {
+    format!("'{}'", s.replace('\'', "'\\''"))
+}
+
 #[derive(Debug)]
 pub struct Session {
     pub name: String,
@@ -314,6 +993,8 @@ pub enum SessionStatus {
     Done,
     /// Session failed to start (❌ character, set by ccx)
     Failed,
+    /// Session is blocked on a prompt ccx escalated to a person (❓ character, set by ccx)
+    WaitingForInput,
     /// Status could not be determined
     Unknown,
 }
@@ -324,6 +1005,7 @@ impl std::fmt::Display for SessionStatus {
             SessionStatus::InProgress => write!(f, "in-progress"),
             SessionStatus::Done => write!(f, "done"),
             SessionStatus::Failed => write!(f, "failed"),
+            SessionStatus::WaitingForInput => write!(f, "waiting-for-input"),
             SessionStatus::Unknown => write!(f, "unknown"),
         }
     }
@@ -342,6 +1024,10 @@ fn is_braille_spinner(c: char) -> bool {
 const DONE_CHAR: char = '✳';
 /// Failed indicator character (set by ccx when session fails to start)
 const FAILED_CHAR: char = '❌';
+/// Waiting indicator character (set by ccx when a prompt needs a person)
+const WAITING_CHAR: char = '❓';
+/// Working indicator character (set by ccx for headless sessions)
+const WORKING_CHAR: char = '⠐';
 
 /// Parse the session status from a pane title
 pub fn parse_status_from_title(title: &str) -> SessionStatus {
@@ -358,6 +1044,9 @@ pub fn parse_status_from_title(title: &str) -> SessionStatus {
         if first_char == FAILED_CHAR {
             return SessionStatus::Failed;
         }
+        if first_char == WAITING_CHAR {
+            return SessionStatus::WaitingForInput;
+        }
         if is_braille_spinner(first_char) {
             return SessionStatus::InProgress;
         }
@@ -366,10 +1055,21 @@ pub fn parse_status_from_title(title: &str) -> SessionStatus {
     SessionStatus::Unknown
 }
 
+/// Mark a session as waiting for a person to answer `what`.
+/// Claude Code replaces the title once it resumes work.
+pub fn set_waiting(session_name: &str, what: &str) {
+    set_pane_title(session_name, &format!("{} Waiting: {}", WAITING_CHAR, what));
+}
+
+/// Mark a session as working on `what`, for sessions whose agent doesn't set a title
+pub fn set_working(session_name: &str, what: &str) {
+    set_pane_title(session_name, &format!("{} {}", WORKING_CHAR, what));
+}
+
 /// Set the pane title for a session
 fn set_pane_title(session_name: &str, title: &str) {
-    let _ = Command::new("tmux")
-        .args(["select-pane", "-t", session_name, "-T", title])
+    let _ = tmux_command()
+        .args(["select-pane", "-t", &agent_pane(session_name), "-T", title])
         .status();
 }
 
@@ -439,6 +1139,108 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_agent_shell_command() {
+        let agent = AgentCommand {
+            session: "ccx-1".to_string(),
+            prompt: "say \"hi\"".to_string(),
+            skip_permissions: true,
+            wrapper: Vec::new(),
+            args: Vec::new(),
+            env: Vec::new(),
+        };
+        assert_eq!(
+            agent.shell_command(None),
+            r#"exec env -u CLAUDECODE -u CLAUDE_CODE_ENTRYPOINT CCX_SESSION='ccx-1' claude --dangerously-skip-permissions 'say "hi"'"#
+        );
+
+        let with_args = AgentCommand {
+            args: vec!["--model".to_string(), "opus".to_string()],
+            ..agent
+        };
+        assert!(
+            with_args
+                .shell_command(None)
+                .ends_with(r#"'say "hi"' '--model' 'opus'"#)
+        );
+
+        let sandboxed = AgentCommand {
+            session: "s".to_string(),
+            prompt: "x".to_string(),
+            skip_permissions: false,
+            wrapper: vec![
+                "bwrap".to_string(),
+                "--bind".to_string(),
+                "/my dir".to_string(),
+                "--".to_string(),
+            ],
+            args: Vec::new(),
+            env: vec![("TOKEN".to_string(), "s3cret".to_string())],
+        };
+        let command = sandboxed.shell_command(Some(Path::new("/h/.ccx/env/s.env")));
+        assert!(command.starts_with(
+            ". '/h/.ccx/env/s.env'; rm -f '/h/.ccx/env/s.env'; exec 'bwrap' '--bind' '/my dir' '--' env "
+        ));
+        assert!(!command.contains("s3cret"));
+    }
+
+    /// Run a pane command with `claude` replaced by printf, returning the prompt
+    /// argument it received
+    fn received_prompt(agent: &AgentCommand) -> String {
+        let dir = std::env::temp_dir().join(format!("ccx-test-{}", rand_id()));
+        fs::create_dir_all(&dir).unwrap();
+        let env_file = dir.join("s.env");
+        fs::write(&env_file, agent.env_script()).unwrap();
+        let command = agent
+            .shell_command(Some(&env_file))
+            .replace(
+                "exec env -u CLAUDECODE -u CLAUDE_CODE_ENTRYPOINT",
+                "exec env",
+            )
+            .replace(" claude ", " printf %s ");
+        let output = Command::new("sh").arg("-c").arg(&command).output().unwrap();
+        let _ = fs::remove_dir_all(&dir);
+        String::from_utf8(output.stdout).unwrap()
+    }
+
+    #[test]
+    fn test_prompt_is_not_expanded() {
+        let prompt = "End with `VERDICT: APPROVE`; don't run $(touch /tmp/pwned) or $HOME \"x\"";
+        let agent = AgentCommand {
+            session: "s".to_string(),
+            prompt: prompt.to_string(),
+            ..Default::default()
+        };
+        assert_eq!(received_prompt(&agent), prompt);
+
+        // Long prompts (like a reviewer's diff) go through the env file
+        let long = format!("{}\n{}", prompt, "+ $(x) `y` 'z'\n".repeat(2000));
+        let agent = AgentCommand {
+            prompt: long.clone(),
+            ..agent
+        };
+        assert!(agent.needs_env_file());
+        assert!(agent.shell_command(Some(Path::new("/e"))).len() < MAX_INLINE_PROMPT);
+        assert_eq!(received_prompt(&agent), long);
+    }
+
+    #[test]
+    fn test_parse_pane_exit() {
+        assert_eq!(parse_pane_exit("0::\n"), None);
+        assert_eq!(parse_pane_exit("1:0:"), Some(0));
+        assert_eq!(parse_pane_exit("1:3:"), Some(3));
+        assert_eq!(parse_pane_exit("1::15"), Some(143));
+        assert_eq!(parse_pane_exit(""), None);
+    }
+
+    #[test]
+    fn test_parse_status_waiting() {
+        assert_eq!(
+            parse_status_from_title("❓ Waiting: Bash(rm -rf target)"),
+            SessionStatus::WaitingForInput
+        );
+    }
+
     #[test]
     fn test_parse_status_unknown() {
         assert_eq!(parse_status_from_title(""), SessionStatus::Unknown);
@@ -449,6 +1251,12 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_shell_quote() {
+        assert_eq!(shell_quote("plain"), "'plain'");
+        assert_eq!(shell_quote("it's"), "'it'\\''s'");
+    }
+
     #[test]
     fn test_session_status_display() {
         assert_eq!(format!("{}", SessionStatus::InProgress), "in-progress");
@@ -456,4 +1264,27 @@ mod tests {
         assert_eq!(format!("{}", SessionStatus::Failed), "failed");
         assert_eq!(format!("{}", SessionStatus::Unknown), "unknown");
     }
+
+    #[test]
+    fn test_parse_pane() {
+        let pane = parse_pane("%3\twork\t1\t0\t4242\t/src/app\t✳ Fix\tthe parser").unwrap();
+        assert_eq!(pane.id, "%3");
+        assert_eq!(pane.target, "work:1.0");
+        assert_eq!(pane.pid, 4242);
+        assert_eq!(pane.cwd, "/src/app");
+        assert_eq!(pane.title, "✳ Fix\tthe parser");
+        assert!(!pane.other_server);
+
+        assert!(parse_pane("%4\twork\t1\t1\t4243\t/src/app\t").is_some());
+        assert!(parse_pane("%5\twork").is_none());
+    }
+
+    #[test]
+    fn test_attach_command() {
+        assert_eq!(
+            attach_command("/tmp/tmux-0/ccx", "ccx-1", true),
+            "'env' '-u' 'TMUX' 'tmux' '-S' '/tmp/tmux-0/ccx' 'attach-session' '-r' '-t' 'ccx-1'"
+        );
+        assert!(!attach_command("/tmp/tmux-0/ccx", "ccx-1", false).contains("'-r'"));
+    }
 }
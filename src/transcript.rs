@@ -0,0 +1,77 @@
+use crate::config::expand_tilde;
+use serde_json::Value;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, UNIX_EPOCH};
+
+/// Directory Claude Code stores transcripts in for a working directory:
+/// ~/.claude/projects/<cwd with non-alphanumerics replaced by '-'>
+fn project_dir(cwd: &str) -> PathBuf {
+    let slug: String = cwd
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '-' })
+        .collect();
+    expand_tilde("~/.claude/projects").join(slug)
+}
+
+/// Find the transcript most recently written by a session started in `cwd` at `since`
+pub fn find(cwd: &str, since: u64) -> Option<PathBuf> {
+    let since = UNIX_EPOCH + Duration::from_secs(since);
+    fs::read_dir(project_dir(cwd))
+        .ok()?
+        .filter_map(|e| e.ok())
+        .filter(|e| e.path().extension().is_some_and(|ext| ext == "jsonl"))
+        .filter_map(|e| Some((e.metadata().ok()?.modified().ok()?, e.path())))
+        .filter(|(modified, _)| *modified >= since)
+        .max_by_key(|(modified, _)| *modified)
+        .map(|(_, path)| path)
+}
+
+/// The text of the last assistant message in a transcript file
+pub fn final_message(path: &Path) -> Option<String> {
+    let content = fs::read_to_string(path).ok()?;
+    content.lines().filter_map(assistant_text).next_back()
+}
+
+/// Extract the text blocks of an assistant message line, if it has any
+fn assistant_text(line: &str) -> Option<String> {
+    let entry: Value = serde_json::from_str(line).ok()?;
+    if entry.get("type")?.as_str()? != "assistant" {
+        return None;
+    }
+    let texts: Vec<&str> = entry
+        .pointer("/message/content")?
+        .as_array()?
+        .iter()
+        .filter(|block| block.get("type").and_then(Value::as_str) == Some("text"))
+        .filter_map(|block| block.get("text")?.as_str())
+        .collect();
+    if texts.is_empty() {
+        return None;
+    }
+    Some(texts.join("\n").trim().to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_project_dir_slug() {
+        let dir = project_dir("/home/me/src/my.app");
+        assert!(dir.ends_with("-home-me-src-my-app"));
+    }
+
+    #[test]
+    fn test_assistant_text() {
+        let line = r#"{"type":"assistant","message":{"role":"assistant","content":[{"type":"text","text":"All done."},{"type":"tool_use","name":"Bash"}]}}"#;
+        assert_eq!(assistant_text(line).as_deref(), Some("All done."));
+
+        let tool_only =
+            r#"{"type":"assistant","message":{"content":[{"type":"tool_use","name":"Bash"}]}}"#;
+        assert_eq!(assistant_text(tool_only), None);
+
+        let user = r#"{"type":"user","message":{"content":[{"type":"text","text":"hi"}]}}"#;
+        assert_eq!(assistant_text(user), None);
+    }
+}
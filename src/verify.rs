@@ -0,0 +1,145 @@
+use crate::store::{self, VerifyRun};
+use crate::{session, tmux};
+use anyhow::{Context, Result};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// Lines of verification output copied into the session log
+const LOG_LINES: usize = 200;
+
+/// Where the output of a session's latest verification run is kept
+fn output_path(session: &str) -> Result<PathBuf> {
+    let home = std::env::var("HOME").context("HOME not set")?;
+    Ok(Path::new(&home)
+        .join(".ccx")
+        .join("logs")
+        .join(format!("{}.verify.txt", session)))
+}
+
+/// Run `command` with `sh -c` in `dir`, returning its exit code (None if killed by a
+/// signal) and combined stdout and stderr
+fn run_command(command: &str, dir: &str) -> Result<(Option<i32>, String)> {
+    let output = Command::new("sh")
+        .args(["-c", command])
+        .current_dir(dir)
+        .output()
+        .with_context(|| format!("Failed to run verification command '{}'", command))?;
+    let mut text = String::from_utf8_lossy(&output.stdout).to_string();
+    text.push_str(&String::from_utf8_lossy(&output.stderr));
+    Ok((output.status.code(), text))
+}
+
+/// The last `n` lines of `text`
+fn tail(text: &str, n: usize) -> Vec<&str> {
+    let lines: Vec<&str> = text.lines().collect();
+    lines[lines.len().saturating_sub(n)..].to_vec()
+}
+
+/// Message sent back into a session whose verification failed
+fn feedback(command: &str, exit_code: Option<i32>, output_file: &Path) -> String {
+    let status = match exit_code {
+        Some(code) => format!("exited with code {}", code),
+        None => "was killed by a signal".to_string(),
+    };
+    format!(
+        "The verification command `{}` {}. Its output is in {}. \
+         Fix the problems it reports, then commit your changes.",
+        command,
+        status,
+        output_file.display()
+    )
+}
+
+/// Run a completed session's verification command, sending failures back into the
+/// session for another attempt until it passes or its rounds are used up.
+/// Returns whether the session was verified.
+pub fn verify_session(session: &str) -> Result<bool> {
+    loop {
+        let mut record = store::load(session)?.context("Session record disappeared")?;
+        let Some(verify) = record.verify.as_mut() else {
+            return Ok(true);
+        };
+        let command = verify.command.clone();
+
+        tmux::session_log(session, &format!("Verify: running `{}`", command));
+        let (exit_code, output) = run_command(&command, &record.cwd)?;
+        let output_file = output_path(session)?;
+        fs::write(&output_file, &output)
+            .with_context(|| format!("Failed to write {}", output_file.display()))?;
+        for line in tail(&output, LOG_LINES) {
+            tmux::session_log(session, &format!("  | {}", line));
+        }
+        let passed = exit_code == Some(0);
+        tmux::session_log(
+            session,
+            &format!(
+                "Verify: `{}` exited with {}",
+                command,
+                exit_code.map_or("signal".to_string(), |c| c.to_string())
+            ),
+        );
+
+        verify.runs.push(VerifyRun {
+            exit_code,
+            at: store::now(),
+        });
+        let retries = verify.runs.len() as u32 - 1;
+        let max_rounds = verify.max_rounds;
+        if !passed && retries < max_rounds {
+            // The session gets another attempt, so its completion no longer applies
+            record.completed_at = None;
+        }
+        store::save(&record)?;
+
+        if passed {
+            tmux::session_log(session, "Verified");
+            return Ok(true);
+        }
+        if retries >= max_rounds || !tmux::session_exists(session) {
+            tmux::session_log(session, "Verification failed");
+            return Ok(false);
+        }
+
+        tmux::send_keys(session, &feedback(&command, exit_code, &output_file))?;
+        tmux::session_log(
+            session,
+            &format!(
+                "Verify: sent failure back to the session (round {}/{})",
+                retries + 1,
+                max_rounds
+            ),
+        );
+        if !session::wait_for_rework(session) {
+            tmux::session_log(session, "Verification failed: session ended");
+            return Ok(false);
+        }
+        session::record_completion(session);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_run_command() {
+        let (code, output) = run_command("echo out; echo err >&2; exit 3", "/").unwrap();
+        assert_eq!(code, Some(3));
+        assert_eq!(output, "out\nerr\n");
+    }
+
+    #[test]
+    fn test_tail() {
+        assert_eq!(tail("a\nb\nc\n", 2), vec!["b", "c"]);
+        assert_eq!(tail("a", 5), vec!["a"]);
+    }
+
+    #[test]
+    fn test_feedback() {
+        let message = feedback("cargo test", Some(101), Path::new("/tmp/out.txt"));
+        assert!(message.contains("`cargo test` exited with code 101"));
+        assert!(message.contains("/tmp/out.txt"));
+        assert!(!message.contains('\n'));
+    }
+}